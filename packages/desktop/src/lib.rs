@@ -1,14 +1,142 @@
-// Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+use rust_md::{Graph, Note, NoteFilter, NoteSort, TreeFolder, Vault, VaultStats};
+use serde::Serialize;
+use std::path::Path;
+use tauri::{
+  async_runtime::{self, JoinHandle, Mutex},
+  AppHandle, Emitter, State,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+/// Tauri event forwarding the changes of the index, its payload is an `IndexEvent`,
+/// ex: `{ "kind": "updated", "path": "sub/note" }`
+const INDEX_EVENT: &str = "vault://index";
+
+/// The vault opened in the app, with its watcher
+#[derive(Default)]
+struct Desktop {
+  vault: Mutex<Option<OpenVault>>,
+}
+
+struct OpenVault {
+  vault: Vault,
+  /// The watcher and the event forwarding, stopped when another vault is opened
+  tasks: Vec<JoinHandle<()>>,
+}
+
+#[derive(Serialize)]
+struct VaultInfo {
+  name: String,
+  root_path: String,
+  stats: VaultStats,
+}
+
+async fn opened(desktop: &State<'_, Desktop>) -> Result<Vault, String> {
+  desktop
+    .vault
+    .lock()
+    .await
+    .as_ref()
+    .map(|open| open.vault.clone())
+    .ok_or_else(|| "No vault is open".to_string())
+}
+
+/// Index a vault folder and watch it, the vault opened before is closed
+#[tauri::command]
+async fn open_vault(
+  path: String,
+  app: AppHandle,
+  desktop: State<'_, Desktop>,
+) -> Result<VaultInfo, String> {
+  let mut config = rust_md::folder_config(&path).map_err(|err| err.to_string())?;
+  // The owner of the vault sees every note
+  config.private.include = true;
+
+  let name = Path::new(&config.root_path)
+    .file_name()
+    .map(|name| name.to_string_lossy().to_string())
+    .unwrap_or_default();
+  let vault = Vault::open(&name, config.clone()).await?;
+
+  let watched = vault.clone();
+  let watcher = async_runtime::spawn(async move {
+    if let Err(err) = watched.watch().await {
+      eprintln!("Error while watching files: {}", err);
+    }
+  });
+  let mut events = vault.subscribe();
+  let forwarder = async_runtime::spawn(async move {
+    loop {
+      match events.recv().await {
+        Ok(event) => {
+          let _ = app.emit(INDEX_EVENT, event);
+        }
+        Err(RecvError::Lagged(_)) => continue,
+        Err(RecvError::Closed) => break,
+      }
+    }
+  });
+
+  let info = VaultInfo {
+    name,
+    root_path: config.root_path.clone(),
+    stats: vault.stats().await,
+  };
+
+  let previous = desktop.vault.lock().await.replace(OpenVault {
+    vault,
+    tasks: vec![watcher, forwarder],
+  });
+  for task in previous.into_iter().flat_map(|open| open.tasks) {
+    task.abort();
+  }
+
+  Ok(info)
+}
+
+/// Folders and notes of the vault
+#[tauri::command]
+async fn list_notes(desktop: State<'_, Desktop>) -> Result<TreeFolder, String> {
+  Ok(opened(&desktop).await?.tree("").await)
+}
+
+/// A note by its path in the vault or its slug, ex: `sub/note`
+#[tauri::command]
+async fn read_note(path: String, desktop: State<'_, Desktop>) -> Result<Note, String> {
+  opened(&desktop)
+    .await?
+    .get(&path)
+    .await
+    .ok_or_else(|| format!("This file does not exist: {}", path))
+}
+
+#[tauri::command]
+async fn search_notes(
+  filter: NoteFilter,
+  sort: Option<NoteSort>,
+  desktop: State<'_, Desktop>,
+) -> Result<Vec<Note>, String> {
+  let vault = opened(&desktop).await?;
+  Ok(vault.query(&filter, sort.unwrap_or_default()).await)
+}
+
+/// Notes and their links
 #[tauri::command]
-fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
+async fn note_graph(desktop: State<'_, Desktop>) -> Result<Graph, String> {
+  Ok(opened(&desktop).await?.graph().await)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    tauri::Builder::default()
-        .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![greet])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+  tauri::Builder::default()
+    .plugin(tauri_plugin_opener::init())
+    .manage(Desktop::default())
+    .invoke_handler(tauri::generate_handler![
+      open_vault,
+      list_notes,
+      read_note,
+      search_notes,
+      note_graph
+    ])
+    .run(tauri::generate_context!())
+    .expect("error while running tauri application");
 }
@@ -1,49 +1,100 @@
-import { createSignal } from "solid-js";
-import logo from "./assets/logo.svg";
+import { createSignal, For, onCleanup, Show } from "solid-js";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import "./App.css";
 
+type VaultInfo = { name: string; root_path: string };
+type Note = { path: string; name: string; public: boolean; content: string };
+type IndexEvent =
+  | { kind: "updated"; path: string }
+  | { kind: "removed"; path: string }
+  | { kind: "reindexed" };
+
 function App() {
-  const [greetMsg, setGreetMsg] = createSignal("");
-  const [name, setName] = createSignal("");
+  const [vault, setVault] = createSignal<VaultInfo>();
+  const [folder, setFolder] = createSignal("");
+  const [text, setText] = createSignal("");
+  const [notes, setNotes] = createSignal<Note[]>([]);
+  const [note, setNote] = createSignal<Note>();
+  const [error, setError] = createSignal("");
+
+  async function search() {
+    setNotes(await invoke<Note[]>("search_notes", { filter: { text: text() || null } }));
+  }
+
+  async function open(path: string) {
+    setNote(await invoke<Note>("read_note", { path: path.replace(/^\//, "") }));
+  }
 
-  async function greet() {
-    // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
-    setGreetMsg(await invoke("greet", { name: name() }));
+  async function openVault() {
+    try {
+      setVault(await invoke<VaultInfo>("open_vault", { path: folder() }));
+      setError("");
+      await search();
+    } catch (err) {
+      setError(String(err));
+    }
   }
 
+  // The index changes as the files of the vault are edited
+  const unlisten = listen<IndexEvent>("vault://index", async (event) => {
+    await search();
+    const current = note();
+    if (current && "path" in event.payload && `/${event.payload.path}` === current.path) {
+      if (event.payload.kind === "removed") {
+        setNote(undefined);
+      } else {
+        await open(current.path);
+      }
+    }
+  });
+  onCleanup(() => unlisten.then((stop) => stop()));
+
   return (
     <main class="container">
-      <h1>Welcome to Tauri + Solid</h1>
-
-      <div class="row">
-        <a href="https://vite.dev" target="_blank">
-          <img src="/vite.svg" class="logo vite" alt="Vite logo" />
-        </a>
-        <a href="https://tauri.app" target="_blank">
-          <img src="/tauri.svg" class="logo tauri" alt="Tauri logo" />
-        </a>
-        <a href="https://solidjs.com" target="_blank">
-          <img src={logo} class="logo solid" alt="Solid logo" />
-        </a>
-      </div>
-      <p>Click on the Tauri, Vite, and Solid logos to learn more.</p>
-
       <form
         class="row"
         onSubmit={(e) => {
           e.preventDefault();
-          greet();
+          openVault();
         }}
       >
         <input
-          id="greet-input"
-          onChange={(e) => setName(e.currentTarget.value)}
-          placeholder="Enter a name..."
+          onChange={(e) => setFolder(e.currentTarget.value)}
+          placeholder="Path of the vault folder..."
         />
-        <button type="submit">Greet</button>
+        <button type="submit">Open</button>
       </form>
-      <p>{greetMsg()}</p>
+      <p>{error()}</p>
+
+      <Show when={vault()}>
+        <h1>{vault()!.name}</h1>
+        <form
+          class="row"
+          onSubmit={(e) => {
+            e.preventDefault();
+            search();
+          }}
+        >
+          <input onChange={(e) => setText(e.currentTarget.value)} placeholder="Search..." />
+          <button type="submit">Search</button>
+        </form>
+        <ul>
+          <For each={notes()}>
+            {(found) => (
+              <li>
+                <a href="#" onClick={() => open(found.path)}>
+                  {found.path}
+                </a>
+              </li>
+            )}
+          </For>
+        </ul>
+      </Show>
+
+      <Show when={note()}>
+        <article innerHTML={note()!.content} />
+      </Show>
     </main>
   );
 }
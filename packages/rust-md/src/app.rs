@@ -1,52 +1,239 @@
 use crate::{
-  parser::{Link, Metadata},
-  AppState,
+  auth::{self, Access},
+  config::{FeedContent, Scope, UserConfig, DEFAULT_VAULT},
+  dates::NoteDates,
+  feed::{self, Feed, FeedFilter, FeedKind},
+  pages,
+  parser::{self, Link, LinkStyle, Metadata},
+  policy::{self, Visibility},
+  schema::{SchemaViolation, TypedFields},
+  seo::{self, Seo},
+  share::{self, ShareLink, ShareQuery},
+  slug,
+  stats::{self, IndexInfo, RecentNote, VaultStats},
+  templates, Vault,
+};
+use axum::{
+  extract::{Extension, MatchedPath, Request},
+  http::{header, HeaderMap, StatusCode},
+  middleware::{self, Next},
+  response::{Html, IntoResponse, Redirect, Response},
+  routing::{get, post},
+  Json, Router,
 };
-use axum::{routing::get, Json, Router};
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 
+/// A markdown file of the vault, parsed and rendered
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Note {
+  /// Stable id, kept when the note is renamed
+  #[serde(default)]
+  pub id: String,
+  /// Whether the note is visible, from its front matter and the visibility rules
   pub public: bool,
+  /// File name without the extension, ex: `My note`
   pub name: String,
+  /// Path of the note in its url, built with the `slug_style`, ex: `blog/my-note`
   pub slug: String,
+  /// Path relative to the vault root without the extension, ex: `/blog/My note`
   pub path: String,
   pub data: NoteData,
+  /// Rendered html, without the private sections unless they are included
   pub content: String,
 }
 
+impl Note {
+  /// Path of the markdown file relative to the vault root, ex: `sub/note.md`
+  pub fn relative_file_path(&self) -> String {
+    format!(
+      "{}.md",
+      self.path.trim_start_matches('/').trim_end_matches(".md")
+    )
+  }
+}
+
+/// Everything extracted from a note besides its html
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NoteData {
+  /// Front matter of the note
   pub metadata: Metadata,
+  /// Links to other notes, external links are not listed
   pub links: Vec<Link>,
+  /// Local files referenced by the note, relative to the vault root
+  #[serde(default)]
+  pub assets: Vec<String>,
+  #[serde(default)]
+  pub seo: Seo,
+  #[serde(default)]
+  pub dates: NoteDates,
+  /// Fields of the schema of the note type, and their violations
+  #[serde(flatten)]
+  pub typed: TypedFields,
+  /// Assets referenced only from the private sections, hidden from the callers
+  /// that do not read them whatever the config of the index
+  #[serde(skip)]
+  pub(crate) private_assets: Vec<String>,
+  /// Links written only in the private sections, like `private_assets`
+  #[serde(skip)]
+  pub(crate) private_links: Vec<Link>,
+  /// Words of the content with and without the private sections
+  #[serde(skip)]
+  pub(crate) words: usize,
+  #[serde(skip)]
+  pub(crate) public_words: usize,
 }
 
-#[derive(Serialize, Deserialize)]
-struct GraphResponse {
-  nodes: Vec<NodeInfo>,
-  links: Vec<Link>,
+impl NoteData {
+  /// Local files referenced by the note as seen with or without its private sections
+  pub fn visible_assets(&self, include_private: bool) -> Vec<String> {
+    visible(&self.assets, &self.private_assets, include_private)
+  }
+
+  /// Links to other notes as seen with or without the private sections of the note
+  pub fn visible_links(&self, include_private: bool) -> Vec<Link> {
+    visible(&self.links, &self.private_links, include_private)
+  }
+
+  /// Words of the content as seen with or without its private sections
+  pub fn word_count(&self, include_private: bool) -> usize {
+    if include_private {
+      self.words
+    } else {
+      self.public_words
+    }
+  }
+
+  /// Whether the note references the file, with or without its private sections
+  pub fn references(&self, asset: &str, include_private: bool) -> bool {
+    if self.private_assets.iter().any(|private| private == asset) {
+      return include_private;
+    }
+    self.assets.iter().any(|public| public == asset)
+  }
+}
+
+/// The items of the index, `private` being the ones found only in the private sections
+fn visible<T: Clone + PartialEq>(items: &[T], private: &[T], include_private: bool) -> Vec<T> {
+  let mut visible: Vec<T> = items
+    .iter()
+    .filter(|item| include_private || !private.contains(item))
+    .cloned()
+    .collect();
+  if include_private {
+    for item in private {
+      if !visible.contains(item) {
+        visible.push(item.clone());
+      }
+    }
+  }
+  visible
+}
+
+#[derive(Serialize)]
+struct NoteViolations {
+  path: String,
+  r#type: String,
+  violations: Vec<SchemaViolation>,
 }
 
 #[derive(Serialize, Deserialize)]
-pub struct NodeInfo {
-  pub id: String,
-  pub public: bool,
-  pub path: String,
-  pub r#type: String,
+struct VaultInfo {
+  name: String,
+  path: String,
+  notes: usize,
 }
 
-pub async fn create(state: AppState) -> Router {
+/// The default vault is served at the root, every vault under `/vaults/{name}`
+pub async fn create(vaults: Vec<Vault>) -> Router {
   // See https://docs.rs/tower-http/latest/tower_http/cors/index.html
   let cors = tower_http::cors::CorsLayer::new().allow_origin(tower_http::cors::Any);
 
+  // Tokens are shared by every vault, the first one is the default vault
+  let auth_state = vaults[0].clone();
+  let mut router = Router::new().fallback(fallback).route(
+    "/vaults",
+    get({
+      let vaults = vaults.clone();
+      move || get_vaults(vaults)
+    })
+    .route_layer(middleware::from_fn_with_state(auth_state, authenticate)),
+  );
+
+  for state in vaults {
+    if state.name == DEFAULT_VAULT {
+      router = router.merge(vault_routes(&state).with_state(state.clone()));
+    }
+    router = router.nest(
+      &format!("/vaults/{}", state.name),
+      vault_routes(&state).with_state(state),
+    );
+  }
+
+  router.layer(cors)
+}
+
+/// Routes of a single vault
+fn vault_routes(state: &Vault) -> Router<Vault> {
   Router::new()
-    .fallback(fallback)
-    .route("/", get(get_default))
+    .route("/", get(get_home))
     .route("/files", get(get_note_list))
     .route("/files/{*file_path}", get(get_note))
-    .with_state(state)
-    .layer(cors)
+    .route("/n/{id}", get(get_note_by_id))
+    .route("/share/{*file_path}", post(post_share).delete(delete_share))
+    .route("/tree", get(get_tree))
+    .route("/visibility/{*file_path}", get(get_visibility))
+    .route("/feed.xml", get(get_rss_feed))
+    .route("/atom.xml", get(get_atom_feed))
+    .route("/feed.json", get(get_json_feed))
+    .route("/sitemap.xml", get(get_sitemap))
+    .route("/violations", get(get_violations))
+    .route("/tags", get(get_tags))
+    .route("/tags/{*tag}", get(get_tag))
+    .route_layer(middleware::from_fn_with_state(state.clone(), authenticate))
+}
+
+/// Check the token of the request, the handlers get the resulting `Access`
+async fn authenticate(
+  axum::extract::State(state): axum::extract::State<Vault>,
+  mut request: Request,
+  next: Next,
+) -> Response {
+  let auth = state.config.read().await.auth.clone();
+  let authorization = request
+    .headers()
+    .get(header::AUTHORIZATION)
+    .and_then(|authorization| authorization.to_str().ok());
+
+  // Share links work without a token, `get_note` checks them
+  let shared_note = request
+    .extensions()
+    .get::<MatchedPath>()
+    .is_some_and(|path| {
+      path.as_str().ends_with("/files/{*file_path}") || path.as_str().ends_with("/n/{id}")
+    });
+
+  let access = match auth::access(&auth, authorization) {
+    Ok(access) if access.can(Scope::ReadPublic) || shared_note => access,
+    Ok(_) => return unauthorized("A token is required".to_string()),
+    Err(message) => return unauthorized(message),
+  };
+  request.extensions_mut().insert(access);
+  next.run(request).await
+}
+
+fn unauthorized(message: String) -> Response {
+  (
+    StatusCode::UNAUTHORIZED,
+    [(header::WWW_AUTHENTICATE, "Bearer")],
+    Json(ErrorResponse {
+      status: "UNAUTHORIZED".to_string(),
+      code: StatusCode::UNAUTHORIZED.as_u16(),
+      message,
+    }),
+  )
+    .into_response()
 }
 
 /// axum handler for any request that fails to match the router routes.
@@ -57,83 +244,253 @@ struct ErrorResponse {
   code: u16,
   message: String,
 }
-async fn fallback(uri: axum::http::Uri) -> Json<ErrorResponse> {
-  Json(ErrorResponse {
-    status: "NOT_FOUND".to_string(),
-    code: axum::http::StatusCode::NOT_FOUND.as_u16(),
-    message: format!("No route {}", uri),
-  })
+async fn fallback(uri: axum::http::Uri) -> ApiError {
+  not_found(format!("No route {}", uri))
 }
 
-#[derive(Serialize, Deserialize)]
-struct DefaultResponse {
-  status: String,
-  code: i32,
-  message: String,
-}
+/// An error response with its http status, the status is repeated in the body
+type ApiError = (StatusCode, Json<ErrorResponse>);
 
-async fn get_default() -> Result<Json<DefaultResponse>, String> {
-  let default_response = DefaultResponse {
-    status: "OK".to_string(),
-    code: 200,
-    message: String::from("Welcome to rust-md, you can use: '/files' - '/files/file_path'"),
-  };
-  Ok(Json(default_response))
+fn api_error(code: StatusCode, status: &str, message: String) -> ApiError {
+  (
+    code,
+    Json(ErrorResponse {
+      status: status.to_string(),
+      code: code.as_u16(),
+      message,
+    }),
+  )
 }
 
-#[derive(Serialize, Deserialize)]
-struct TestResponse {
-  id: String,
-  html: String,
+#[derive(Serialize)]
+struct HomeResponse {
+  vault: String,
+  stats: VaultStats,
+  index: IndexInfo,
+  recent: Vec<RecentNote>,
 }
 
-async fn get_note_list(
-  axum::extract::State(state): axum::extract::State<AppState>,
-) -> Result<Json<GraphResponse>, String> {
-  // ! DEBUG
-  let start = std::time::Instant::now();
+#[derive(Deserialize)]
+struct HomeQuery {
+  /// Number of recent notes, 10 by default
+  recent: Option<usize>,
+}
 
+/// Statistics of the vault and its recent notes
+async fn get_home(
+  Extension(access): Extension<Access>,
+  axum::extract::Query(query): axum::extract::Query<HomeQuery>,
+  axum::extract::State(state): axum::extract::State<Vault>,
+) -> Json<HomeResponse> {
+  let config = access.config(&*state.config.read().await);
   let notes_guard = state.notes.lock().await;
 
-  let mut nodes = Vec::new();
-  let mut links = Vec::new();
+  Json(HomeResponse {
+    vault: state.name.clone(),
+    stats: stats::vault_stats(&notes_guard, &config),
+    index: state.index.read().await.clone(),
+    recent: stats::recent_notes(
+      &notes_guard,
+      &config,
+      &base_url(&state),
+      query.recent.unwrap_or(10),
+    ),
+  })
+}
 
-  for (file_name, converted_note) in notes_guard.iter() {
-    nodes.push(NodeInfo {
-      id: file_name.clone(),
-      public: converted_note.public,
-      path: format!("/{}", file_name.replace("%20", " ")),
-      r#type: "note".to_string(),
+async fn get_vaults(vaults: Vec<Vault>) -> Json<Vec<VaultInfo>> {
+  let mut infos = Vec::new();
+  for state in vaults {
+    infos.push(VaultInfo {
+      path: format!("/vaults/{}", state.name),
+      notes: state.notes.lock().await.len(),
+      name: state.name,
     });
+  }
+  Json(infos)
+}
+
+/// Whether the client asked for an html page, ex: a browser
+fn wants_html(headers: &HeaderMap) -> bool {
+  headers
+    .get(header::ACCEPT)
+    .and_then(|accept| accept.to_str().ok())
+    .is_some_and(|accept| accept.contains("text/html"))
+}
+
+/// Prefix of the urls of the vault
+fn base_url(state: &Vault) -> String {
+  if state.name == DEFAULT_VAULT {
+    String::new()
+  } else {
+    format!("/vaults/{}", state.name)
+  }
+}
+
+fn forbidden(message: String) -> ApiError {
+  api_error(StatusCode::FORBIDDEN, "FORBIDDEN", message)
+}
+
+fn not_found(message: String) -> ApiError {
+  api_error(StatusCode::NOT_FOUND, "NOT_FOUND", message)
+}
+
+/// Render a page through the templates of the vault
+async fn render_page<T: Serialize>(
+  state: &Vault,
+  template: &str,
+  title: &str,
+  content: &str,
+  page: &T,
+) -> Result<Response, ApiError> {
+  state
+    .templates
+    .read()
+    .await
+    .render(template, title, content, page)
+    .map(|html| Html(html).into_response())
+    .map_err(|err| internal_error(format!("Failed to render {}: {:#}", template, err)))
+}
+
+async fn get_note_list(
+  Extension(access): Extension<Access>,
+  headers: HeaderMap,
+  axum::extract::State(state): axum::extract::State<Vault>,
+) -> Result<Response, ApiError> {
+  // ! DEBUG
+  let start = std::time::Instant::now();
 
-    // ! Extract links from the note
-    links.extend(converted_note.data.links.clone());
+  if wants_html(&headers) && state.templates.read().await.enabled() {
+    return get_folder("", &state, &access).await;
   }
 
+  let config = access.config(&*state.config.read().await);
+  let graph = pages::note_graph(&*state.notes.lock().await, &config);
+
   // ! DEBUG
-  println!(
+  log::info!(
     "{} {} /files in {:?}",
     "[webserver]".cyan(),
     " GET ".on_blue(),
     start.elapsed(),
   );
 
-  Ok(Json(GraphResponse { nodes, links }))
+  Ok(Json(graph).into_response())
+}
+
+/// A note with the links to its folders and siblings
+#[derive(Serialize)]
+struct NoteResponse<'a> {
+  #[serde(flatten)]
+  note: &'a Note,
+  navigation: pages::Navigation,
+}
+
+/// Representation of a note
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+  /// The `Note` with its html content
+  Json,
+  /// The html content alone
+  Html,
+  /// The html page rendered with the templates, if any
+  Page,
+  Markdown,
+  /// Text without formatting
+  Text,
+}
+
+#[derive(Deserialize)]
+struct FormatQuery {
+  format: Option<Format>,
+  /// Keep the frontmatter of the markdown, true by default
+  frontmatter: Option<bool>,
 }
 
+/// The format asked by `?format=`, else by the `Accept` header
+fn negotiate(query: &FormatQuery, headers: &HeaderMap) -> Format {
+  if let Some(format) = query.format {
+    return format;
+  }
+
+  let accept = headers
+    .get(header::ACCEPT)
+    .and_then(|accept| accept.to_str().ok())
+    .unwrap_or_default();
+  if accept.contains("text/markdown") {
+    Format::Markdown
+  } else if accept.contains("text/html") {
+    Format::Page
+  } else if accept.contains("text/plain") {
+    Format::Text
+  } else {
+    Format::Json
+  }
+}
+
+fn internal_error(message: String) -> ApiError {
+  api_error(
+    StatusCode::INTERNAL_SERVER_ERROR,
+    "INTERNAL_SERVER_ERROR",
+    message,
+  )
+}
+
+/// The note as the caller sees it: rendered again when the caller reads the private
+/// sections and links that the index left out, or the other way around
+async fn scoped_note(
+  mut note: Note,
+  config: &UserConfig,
+  indexed_config: &UserConfig,
+) -> Result<Note, ApiError> {
+  if config.private.include == indexed_config.private.include {
+    return Ok(note);
+  }
+
+  let path = Path::new(&config.root_path).join(note.relative_file_path());
+  let path = path.to_string_lossy().replace('\\', "/");
+  let source = tokio::fs::read_to_string(&path)
+    .await
+    .map_err(|err| internal_error(format!("Failed to read file '{}': {}", path, err)))?;
+  let (content, _, links) =
+    parser::render_markdown(&path, &note.name, &source, config, LinkStyle::Server)
+      .map_err(|err| internal_error(format!("Failed to convert '{}': {}", path, err)))?;
+
+  note.content = content;
+  note.data.links = links;
+  note.data.assets = note.data.visible_assets(config.private.include);
+  Ok(note)
+}
+
+/// A note, a folder of notes or a file referenced by a visible note.
+///
+/// A share link lets its note through even when it is private, and the files
+/// it references when the link shares them.
 async fn get_note(
+  Extension(access): Extension<Access>,
+  uri: axum::http::Uri,
+  headers: HeaderMap,
+  axum::extract::Query(query): axum::extract::Query<FormatQuery>,
+  axum::extract::Query(share_query): axum::extract::Query<ShareQuery>,
   axum::extract::Path(file_path): axum::extract::Path<String>,
-  axum::extract::State(state): axum::extract::State<AppState>,
-) -> Result<Json<Note>, Json<ErrorResponse>> {
+  axum::extract::State(state): axum::extract::State<Vault>,
+) -> Result<Response, ApiError> {
   // ! DEBUG
   let start = std::time::Instant::now();
 
-  let notes_guard = state.notes.lock().await;
+  let share_link = share_query.link();
 
-  if let Some(note) = notes_guard.get(&file_path) {
+  // The note is copied, the files are read and rendered without holding the index
+  let found = {
+    let notes = state.notes.lock().await;
+    slug::find_note(&notes, &file_path).map(|key| notes[key].clone())
+  };
+
+  if let Some(note) = found {
     // ! DEBUG
 
-    println!(
+    log::info!(
       "{} {} /{} in {:?}",
       "[webserver]".cyan(),
       " GET ".on_blue(),
@@ -141,20 +498,607 @@ async fn get_note(
       start.elapsed(),
     );
 
+    let checked = match share_link {
+      Some(Ok(link)) if link.note == note.id => {
+        Some(state.shares.lock().await.check(&link).map(|()| link))
+      }
+      Some(Ok(_)) => Some(Err("This share link is for another note".to_string())),
+      Some(Err(message)) => Some(Err(message)),
+      None => None,
+    };
+    // Only a genuine link to this very note stands in for a token
+    let shared = match checked {
+      Some(Ok(link)) => Some(link),
+      _ if !access.can(Scope::ReadPublic) => {
+        return Ok(unauthorized("A token is required".to_string()))
+      }
+      Some(Err(message)) => return Err(forbidden(message)),
+      None => None,
+    };
+
     // ! HANDLE PRIVATE NOTES
-    if !note.public && !state.config.private.include {
-      return Err(Json(ErrorResponse {
-        status: "FORBIDDEN".to_string(),
-        code: axum::http::StatusCode::FORBIDDEN.as_u16(),
-        message: format!("This file is private: {}", file_path),
-      }));
+    let indexed_config = state.config.read().await.clone();
+    let config = access.config(&indexed_config);
+    if !note.public && !config.private.include && shared.is_none() {
+      return Err(forbidden(format!("This file is private: {}", file_path)));
     }
-    Ok(Json(note.clone()))
-  } else {
-    Err(Json(ErrorResponse {
-      status: "NOT_FOUND".to_string(),
-      code: axum::http::StatusCode::NOT_FOUND.as_u16(),
-      message: format!("This file does not exist: {}", file_path),
-    }))
+
+    let format = match negotiate(&query, &headers) {
+      Format::Page if !state.templates.read().await.enabled() => Format::Html,
+      format => format,
+    };
+    let note = match format {
+      Format::Json | Format::Html | Format::Page => {
+        scoped_note(note, &config, &indexed_config).await?
+      }
+      Format::Markdown | Format::Text => note,
+    };
+    let note = &note;
+
+    let mut response = match format {
+      Format::Json => {
+        let navigation =
+          pages::navigation(&*state.notes.lock().await, note, &config, &base_url(&state));
+        Ok(Json(NoteResponse { note, navigation }).into_response())
+      }
+      Format::Html => Ok(Html(note.content.clone()).into_response()),
+      Format::Page => {
+        let mut page =
+          pages::note_page(&*state.notes.lock().await, note, &config, &base_url(&state));
+        let vault_url = format!(
+          "{}{}",
+          site_url(&config.site.base_url, &headers),
+          base_url(&state)
+        );
+        page.meta = Some(seo::seo_meta(
+          note,
+          &config,
+          pages::note_url(&vault_url, note),
+          |asset| pages::page_url(&vault_url, asset),
+        ));
+        render_page(&state, templates::NOTE, &page.title, &note.content, &page).await
+      }
+      Format::Markdown | Format::Text => {
+        // Read again, only the html is kept in the index
+        let path = Path::new(&config.root_path).join(note.relative_file_path());
+        let path = path.to_string_lossy().replace('\\', "/");
+        let source = tokio::fs::read_to_string(&path)
+          .await
+          .map_err(|err| internal_error(format!("Failed to read file '{}': {}", path, err)))?;
+
+        let (content, content_type) = if format == Format::Markdown {
+          let frontmatter = query.frontmatter.unwrap_or(true);
+          (
+            parser::redacted_markdown(&path, &source, &config, frontmatter),
+            "text/markdown; charset=utf-8",
+          )
+        } else {
+          (
+            parser::markdown_to_text(&path, &source, &config),
+            "text/plain; charset=utf-8",
+          )
+        };
+        let content = content
+          .map_err(|err| internal_error(format!("Failed to convert '{}': {}", path, err)))?;
+
+        Ok(([(header::CONTENT_TYPE, content_type)], content).into_response())
+      }
+    }?;
+
+    // The assets are linked relative to the note, without the query of the link
+    if let Some(link) = shared.filter(|link| link.assets) {
+      let cookie = format!(
+        "{}={}; Path={}/files; Max-Age={}; HttpOnly; SameSite=Lax",
+        share::ASSETS_COOKIE,
+        link.query(),
+        base_url(&state),
+        link.expires - chrono::Utc::now().timestamp()
+      );
+      if let Ok(cookie) = header::HeaderValue::from_str(&cookie) {
+        response.headers_mut().append(header::SET_COOKIE, cookie);
+      }
+    }
+    return Ok(response);
   }
+
+  let asset_share = match &share_link {
+    Some(Ok(link)) => Some(link.clone()),
+    _ => assets_cookie(&headers),
+  };
+  if let Some(response) = get_asset(&file_path, &state, &access, asset_share.as_ref()).await {
+    return Ok(response);
+  }
+  // A share link only opens its note and the files of the note
+  if !access.can(Scope::ReadPublic) {
+    return Ok(unauthorized("A token is required".to_string()));
+  }
+
+  // Old links keep working after a rename
+  let slug_style = state.config.read().await.slug_style;
+  let renamed = state
+    .ids
+    .lock()
+    .await
+    .renamed_path(&file_path, slug_style)
+    .map(str::to_string);
+  if let Some(path) = renamed {
+    let query = uri
+      .query()
+      .map(|query| format!("?{}", query))
+      .unwrap_or_default();
+    let url = match state.notes.lock().await.get(&path) {
+      Some(note) => pages::note_url(&base_url(&state), note),
+      None => pages::page_url(&base_url(&state), &path),
+    };
+    return Ok(Redirect::permanent(&format!("{}{}", url, query)).into_response());
+  }
+
+  if wants_html(&headers) && state.templates.read().await.enabled() {
+    return get_folder(&file_path, &state, &access).await;
+  }
+
+  Err(not_found(format!(
+    "This file does not exist: {}",
+    file_path
+  )))
+}
+
+/// Folders of the vault with their visible notes, for a file explorer
+async fn get_tree(
+  Extension(access): Extension<Access>,
+  axum::extract::State(state): axum::extract::State<Vault>,
+) -> Json<pages::TreeFolder> {
+  let config = access.config(&*state.config.read().await);
+  let notes_guard = state.notes.lock().await;
+  Json(pages::folder_tree(&notes_guard, &config, &base_url(&state)))
+}
+
+/// A note by its stable id, in any format of `/files/{path}`
+async fn get_note_by_id(
+  access: Extension<Access>,
+  uri: axum::http::Uri,
+  headers: HeaderMap,
+  query: axum::extract::Query<FormatQuery>,
+  share_query: axum::extract::Query<ShareQuery>,
+  axum::extract::Path(id): axum::extract::Path<String>,
+  state: axum::extract::State<Vault>,
+) -> Result<Response, ApiError> {
+  let file_path = state
+    .notes
+    .lock()
+    .await
+    .iter()
+    .find(|(_, note)| note.id == id)
+    .map(|(file_path, _)| file_path.clone());
+
+  match file_path {
+    Some(file_path) => {
+      get_note(
+        access,
+        uri,
+        headers,
+        query,
+        share_query,
+        axum::extract::Path(file_path),
+        state,
+      )
+      .await
+    }
+    None => Err(not_found(format!("No note with the id: {}", id))),
+  }
+}
+
+/// Index page of a folder
+async fn get_folder(folder: &str, state: &Vault, access: &Access) -> Result<Response, ApiError> {
+  let config = access.config(&*state.config.read().await);
+  let notes_guard = state.notes.lock().await;
+
+  let folder = slug::find_folder(&notes_guard, folder, config.slug_style)
+    .unwrap_or_else(|| folder.to_string());
+  let Some(page) = pages::folder_page(&notes_guard, &folder, &config, &base_url(state)) else {
+    return Err(not_found(format!("This file does not exist: {}", folder)));
+  };
+  render_page(state, templates::FOLDER, &page.name, "", &page).await
+}
+
+/// Files like images are only served when a visible note references them,
+/// or a note shared along with its assets
+async fn get_asset(
+  file_path: &str,
+  state: &Vault,
+  access: &Access,
+  share: Option<&ShareLink>,
+) -> Option<Response> {
+  let config = access.config(&*state.config.read().await);
+  let shared_note = match share {
+    Some(link) if link.assets && state.shares.lock().await.check(link).is_ok() => {
+      Some(link.note.as_str())
+    }
+    _ => None,
+  };
+  let referenced = state.notes.lock().await.values().any(|note| {
+    let readable = (access.can(Scope::ReadPublic) && pages::is_visible(note, &config))
+      || shared_note == Some(note.id.as_str());
+    readable && note.data.references(file_path, config.private.include)
+  });
+  if !referenced || file_path.split('/').any(|part| part == "..") {
+    return None;
+  }
+
+  let path = Path::new(&config.root_path).join(file_path);
+  if crate::matcher::IgnoreMatcher::new(&config).is_ignored(&path, false) {
+    return None;
+  }
+  let content = tokio::fs::read(&path).await.ok()?;
+
+  Some(([(header::CONTENT_TYPE, content_type(&path))], content).into_response())
+}
+
+fn content_type(path: &Path) -> &'static str {
+  let extension = path
+    .extension()
+    .and_then(|ext| ext.to_str())
+    .unwrap_or_default()
+    .to_lowercase();
+  match extension.as_str() {
+    "png" => "image/png",
+    "jpg" | "jpeg" => "image/jpeg",
+    "gif" => "image/gif",
+    "svg" => "image/svg+xml",
+    "webp" => "image/webp",
+    "avif" => "image/avif",
+    "ico" => "image/x-icon",
+    "pdf" => "application/pdf",
+    "mp3" => "audio/mpeg",
+    "mp4" => "video/mp4",
+    "webm" => "video/webm",
+    "txt" => "text/plain; charset=utf-8",
+    "css" => "text/css; charset=utf-8",
+    "json" => "application/json",
+    _ => "application/octet-stream",
+  }
+}
+
+#[derive(Deserialize)]
+struct FeedQuery {
+  tag: Option<String>,
+  folder: Option<String>,
+  /// Overrides `site.feed_content`
+  content: Option<FeedContent>,
+}
+
+async fn get_rss_feed(
+  headers: HeaderMap,
+  query: axum::extract::Query<FeedQuery>,
+  state: axum::extract::State<Vault>,
+) -> Response {
+  get_feed(FeedKind::Rss, headers, query, state).await
+}
+
+async fn get_atom_feed(
+  headers: HeaderMap,
+  query: axum::extract::Query<FeedQuery>,
+  state: axum::extract::State<Vault>,
+) -> Response {
+  get_feed(FeedKind::Atom, headers, query, state).await
+}
+
+async fn get_json_feed(
+  headers: HeaderMap,
+  query: axum::extract::Query<FeedQuery>,
+  state: axum::extract::State<Vault>,
+) -> Response {
+  get_feed(FeedKind::Json, headers, query, state).await
+}
+
+/// Absolute url of the site, from the config or the `Host` header
+fn site_url(base_url: &Option<String>, headers: &HeaderMap) -> String {
+  base_url.clone().unwrap_or_else(|| {
+    let host = headers
+      .get(header::HOST)
+      .and_then(|host| host.to_str().ok())
+      .unwrap_or("localhost");
+    format!("http://{}", host)
+  })
+}
+
+/// Recent public notes of the vault, of a tag or of a folder
+async fn get_feed(
+  kind: FeedKind,
+  headers: HeaderMap,
+  axum::extract::Query(query): axum::extract::Query<FeedQuery>,
+  axum::extract::State(state): axum::extract::State<Vault>,
+) -> Response {
+  let config = state.config.read().await.clone();
+  let vault_url = format!(
+    "{}{}",
+    site_url(&config.site.base_url, &headers),
+    base_url(&state)
+  );
+
+  let filter = FeedFilter {
+    tag: query.tag,
+    folder: query.folder,
+  };
+  let (home_url, feed_url) = match (&filter.tag, &filter.folder) {
+    (Some(tag), _) => (
+      format!("{}/tags/{}", vault_url, slug::encode_path(tag)),
+      format!(
+        "{}/{}?tag={}",
+        vault_url,
+        kind.file_name(),
+        slug::encode_component(tag)
+      ),
+    ),
+    (None, Some(folder)) => (
+      pages::folder_url(&vault_url, folder.trim_matches('/'), &config),
+      format!(
+        "{}/{}?folder={}",
+        vault_url,
+        kind.file_name(),
+        slug::encode_component(folder)
+      ),
+    ),
+    (None, None) => (
+      pages::folder_url(&vault_url, "", &config),
+      format!("{}/{}", vault_url, kind.file_name()),
+    ),
+  };
+
+  let notes_guard = state.notes.lock().await;
+  let feed = Feed {
+    title: feed::feed_title(&config, &filter),
+    home_url,
+    feed_url,
+    entries: feed::feed_entries(
+      &notes_guard,
+      &config,
+      &filter,
+      query.content.unwrap_or(config.site.feed_content),
+      LinkStyle::Server,
+      |note| pages::note_url(&vault_url, note),
+    ),
+  };
+
+  (
+    [(header::CONTENT_TYPE, kind.content_type())],
+    feed::render(kind, &feed),
+  )
+    .into_response()
+}
+
+/// Visible notes whose frontmatter does not match the schema of their type
+async fn get_violations(
+  Extension(access): Extension<Access>,
+  axum::extract::State(state): axum::extract::State<Vault>,
+) -> Json<Vec<NoteViolations>> {
+  let config = access.config(&*state.config.read().await);
+  let notes_guard = state.notes.lock().await;
+
+  let mut found: Vec<NoteViolations> = notes_guard
+    .values()
+    .filter(|note| pages::is_visible(note, &config) && !note.data.typed.violations.is_empty())
+    .map(|note| NoteViolations {
+      path: note.path.clone(),
+      r#type: note.data.metadata.r#type.clone().unwrap_or_default(),
+      violations: note.data.typed.violations.clone(),
+    })
+    .collect();
+  found.sort_by(|a, b| a.path.cmp(&b.path));
+
+  Json(found)
+}
+
+/// Public notes of the vault, for search engines
+async fn get_sitemap(
+  headers: HeaderMap,
+  axum::extract::State(state): axum::extract::State<Vault>,
+) -> Response {
+  let config = state.config.read().await.clone();
+  let vault_url = format!(
+    "{}{}",
+    site_url(&config.site.base_url, &headers),
+    base_url(&state)
+  );
+
+  let notes_guard = state.notes.lock().await;
+  let entries = seo::sitemap_entries(&notes_guard, &config, |note| {
+    pages::note_url(&vault_url, note)
+  });
+
+  (
+    [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+    seo::render_sitemap(&entries),
+  )
+    .into_response()
+}
+
+/// The tags of the visible notes
+async fn get_tags(
+  Extension(access): Extension<Access>,
+  headers: HeaderMap,
+  axum::extract::State(state): axum::extract::State<Vault>,
+) -> Result<Response, ApiError> {
+  let config = access.config(&*state.config.read().await);
+  let page = pages::tags_page(&*state.notes.lock().await, None, &config, &base_url(&state));
+
+  if wants_html(&headers) && state.templates.read().await.enabled() {
+    return render_page(&state, templates::TAGS, "Tags", "", &page).await;
+  }
+  Ok(Json(page).into_response())
+}
+
+/// The visible notes having a tag
+async fn get_tag(
+  Extension(access): Extension<Access>,
+  headers: HeaderMap,
+  axum::extract::Path(tag): axum::extract::Path<String>,
+  axum::extract::State(state): axum::extract::State<Vault>,
+) -> Result<Response, ApiError> {
+  let config = access.config(&*state.config.read().await);
+  let page = pages::tags_page(
+    &*state.notes.lock().await,
+    Some(&tag),
+    &config,
+    &base_url(&state),
+  );
+
+  if wants_html(&headers) && state.templates.read().await.enabled() {
+    return render_page(&state, templates::TAGS, &format!("#{}", tag), "", &page).await;
+  }
+  Ok(Json(page).into_response())
+}
+
+#[derive(Serialize, Deserialize)]
+struct VisibilityResponse {
+  path: String,
+  #[serde(flatten)]
+  visibility: Visibility,
+}
+
+/// Explain which rule made a note public or private, for the admins
+async fn get_visibility(
+  Extension(access): Extension<Access>,
+  axum::extract::Path(file_path): axum::extract::Path<String>,
+  axum::extract::State(state): axum::extract::State<Vault>,
+) -> Result<Json<VisibilityResponse>, ApiError> {
+  if !access.can(Scope::Admin) {
+    return Err(forbidden("The admin scope is required".to_string()));
+  }
+
+  let notes_guard = state.notes.lock().await;
+
+  let Some(note) = slug::find_note(&notes_guard, &file_path).map(|key| &notes_guard[key]) else {
+    return Err(not_found(format!(
+      "This file does not exist: {}",
+      file_path
+    )));
+  };
+
+  let path = note.relative_file_path();
+  let visibility = policy::evaluate(
+    &state.config.read().await.private,
+    &path,
+    &note.data.metadata,
+  );
+
+  Ok(Json(VisibilityResponse { path, visibility }))
+}
+
+/// The share link of the assets cookie, set when a note is opened with a share link
+fn assets_cookie(headers: &HeaderMap) -> Option<ShareLink> {
+  let prefix = format!("{}=", share::ASSETS_COOKIE);
+  let value = headers
+    .get_all(header::COOKIE)
+    .iter()
+    .filter_map(|cookies| cookies.to_str().ok())
+    .flat_map(|cookies| cookies.split(';'))
+    .find_map(|cookie| cookie.trim().strip_prefix(&prefix))?;
+  let uri: axum::http::Uri = format!("/?{}", value).parse().ok()?;
+  let axum::extract::Query(query) = axum::extract::Query::<ShareQuery>::try_from_uri(&uri).ok()?;
+  query.link()?.ok()
+}
+
+#[derive(Default, Deserialize)]
+struct ShareRequest {
+  /// Validity of the link in seconds, 7 days by default
+  expires_in: Option<i64>,
+  /// Share the files referenced by the note too, false by default
+  assets: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct ShareResponse {
+  /// Id of the link, to revoke it
+  id: String,
+  url: String,
+  expires: chrono::DateTime<chrono::Utc>,
+  /// Urls of the shared files, when they are shared
+  assets: Vec<String>,
+}
+
+/// Sign a link to a note for someone without a token, only the notes the
+/// caller can read can be shared
+async fn post_share(
+  Extension(access): Extension<Access>,
+  headers: HeaderMap,
+  axum::extract::Path(file_path): axum::extract::Path<String>,
+  axum::extract::State(state): axum::extract::State<Vault>,
+  request: Option<Json<ShareRequest>>,
+) -> Result<Json<ShareResponse>, ApiError> {
+  let Json(request) = request.unwrap_or_default();
+  let config = access.config(&*state.config.read().await);
+  let notes_guard = state.notes.lock().await;
+
+  let Some(note) = slug::find_note(&notes_guard, &file_path).map(|key| &notes_guard[key]) else {
+    return Err(not_found(format!(
+      "This file does not exist: {}",
+      file_path
+    )));
+  };
+  if !pages::is_visible(note, &config) {
+    return Err(forbidden(format!("This file is private: {}", file_path)));
+  }
+  let expires_in = request.expires_in.unwrap_or(share::DEFAULT_EXPIRY);
+  if expires_in <= 0 {
+    return Err(api_error(
+      StatusCode::BAD_REQUEST,
+      "BAD_REQUEST",
+      "expires_in must be a positive number of seconds".to_string(),
+    ));
+  }
+
+  let assets = request.assets.unwrap_or(false);
+  let link = state.shares.lock().await.sign(&note.id, assets, expires_in);
+  let vault_url = format!(
+    "{}{}",
+    site_url(&config.site.base_url, &headers),
+    base_url(&state)
+  );
+
+  log::info!(
+    "{} {} /{} shared until {} by {}",
+    "[webserver]".cyan(),
+    " POST ".on_green(),
+    note.name,
+    link.expires_at(),
+    access.token.as_deref().unwrap_or("anonymous"),
+  );
+
+  Ok(Json(ShareResponse {
+    id: link.share.clone(),
+    url: format!("{}?{}", pages::note_url(&vault_url, note), link.query()),
+    expires: link.expires_at(),
+    assets: if assets {
+      note
+        .data
+        .visible_assets(config.private.include)
+        .iter()
+        .map(|asset| format!("{}?{}", pages::page_url(&vault_url, asset), link.query()))
+        .collect()
+    } else {
+      Vec::new()
+    },
+  }))
+}
+
+#[derive(Serialize)]
+struct RevokeResponse {
+  id: String,
+  /// False when the link was already revoked
+  revoked: bool,
+}
+
+/// Refuse a share link from now on, for the callers reading the private notes
+async fn delete_share(
+  Extension(access): Extension<Access>,
+  axum::extract::Path(id): axum::extract::Path<String>,
+  axum::extract::State(state): axum::extract::State<Vault>,
+) -> Result<Json<RevokeResponse>, ApiError> {
+  if !access.can(Scope::ReadPrivate) {
+    return Err(forbidden("The read-private scope is required".to_string()));
+  }
+
+  let revoked = state.shares.lock().await.revoke(&id);
+  Ok(Json(RevokeResponse { id, revoked }))
 }
@@ -0,0 +1,72 @@
+use crate::config::{AuthConfig, Scope, UserConfig};
+
+/// Who sent a request and what they may do, added to the requests by the server
+#[derive(Debug, Clone)]
+pub struct Access {
+  /// Name of the token, `None` for anonymous requests
+  pub token: Option<String>,
+  pub scopes: Vec<Scope>,
+  /// No token is defined: every scope is allowed and `private.include` decides
+  /// whether the private notes are shown
+  pub open: bool,
+}
+
+impl Access {
+  pub fn can(&self, scope: Scope) -> bool {
+    self.open || self.scopes.iter().any(|granted| granted.allows(scope))
+  }
+
+  /// Config of the vault as the caller sees it, when tokens are defined
+  /// `private.include` is set only for the callers reading the private notes
+  pub fn config(&self, config: &UserConfig) -> UserConfig {
+    let mut config = config.clone();
+    if !self.open {
+      config.private.include = self.can(Scope::ReadPrivate);
+    }
+    config
+  }
+}
+
+/// Resolve the `Authorization` header of a request, an error when its token is unknown
+pub fn access(auth: &AuthConfig, authorization: Option<&str>) -> Result<Access, String> {
+  if !auth.enabled() {
+    return Ok(Access {
+      token: None,
+      scopes: Vec::new(),
+      open: true,
+    });
+  }
+
+  let Some(authorization) = authorization else {
+    return Ok(Access {
+      token: None,
+      scopes: auth.anonymous.clone(),
+      open: false,
+    });
+  };
+  let token = authorization
+    .strip_prefix("Bearer ")
+    .ok_or_else(|| "Expected an `Authorization: Bearer {token}` header".to_string())?
+    .trim();
+
+  // Every token is compared, so the time taken does not tell which one matched
+  let mut found = None;
+  for known in auth.tokens() {
+    if constant_time_eq(known.token.as_bytes(), token.as_bytes()) && found.is_none() {
+      found = Some(known);
+    }
+  }
+
+  found
+    .map(|known| Access {
+      token: Some(known.name.clone()),
+      scopes: known.scopes.clone(),
+      open: false,
+    })
+    .ok_or_else(|| "Invalid token".to_string())
+}
+
+/// Compare two secrets in a time that only depends on their lengths
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
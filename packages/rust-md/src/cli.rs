@@ -0,0 +1,624 @@
+use crate::{
+  app::{self, Note},
+  config::{load_config, ServerConfig, UserConfig, DEFAULT_VAULT},
+  dates,
+  feed::{FeedFilter, FeedKind},
+  ids::IdCache,
+  reload,
+  search::{self, NoteFilter, NoteSort},
+  server::Listener,
+  service::find_all_notes,
+  site, slug, validation, Vault,
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use colored::Colorize;
+use log::{Level, LevelFilter, Log, Record};
+use serde::Serialize;
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+  process,
+  sync::Arc,
+};
+use tokio::sync::Mutex;
+
+pub const DEFAULT_CONFIG_PATH: &str = "./packages/rust-md/config.yaml";
+pub const DEFAULT_BIND: &str = "127.0.0.1";
+pub const DEFAULT_PORT: u16 = 4000;
+
+/// Serve markdown files like a rest api
+#[derive(Parser, Debug)]
+#[command(name = "rust-md", version)]
+pub struct Cli {
+  /// Path to the config file
+  #[arg(short, long, global = true, env = "RUST_MD_CONFIG", default_value = DEFAULT_CONFIG_PATH)]
+  pub config: String,
+
+  /// Override the root path of the default vault
+  #[arg(short, long, global = true)]
+  pub root: Option<String>,
+
+  /// Vault used by the commands other than `serve`
+  #[arg(long, global = true, default_value = DEFAULT_VAULT)]
+  pub vault: String,
+
+  /// Print the folders and files scanned too
+  #[arg(long, global = true)]
+  pub verbose: bool,
+
+  #[command(subcommand)]
+  pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+  /// Index the vault, watch for changes and serve it (default)
+  Serve(ServeArgs),
+  /// Parse the whole vault once and print statistics
+  Index(IndexArgs),
+  /// Report broken links and other problems
+  Check,
+  /// Export the visible notes as json or as a static site
+  Export(ExportArgs),
+  /// Search notes by tags, type, visibility or text
+  Query(QueryArgs),
+  /// Inspect the configuration
+  Config(ConfigArgs),
+}
+
+#[derive(Args, Debug, Default)]
+pub struct ServeArgs {
+  /// Address to bind the webserver to, `server.host` of the config or 127.0.0.1 by default
+  #[arg(short, long)]
+  pub bind: Option<String>,
+
+  /// Port of the webserver, `server.port` of the config or 4000 by default
+  #[arg(short, long)]
+  pub port: Option<u16>,
+
+  /// Listen on this unix domain socket instead of a port
+  #[arg(long, conflicts_with_all = ["bind", "port"])]
+  pub unix_socket: Option<String>,
+}
+
+impl ServeArgs {
+  /// Where to listen, the options given take precedence over `server` of the config
+  pub fn server_config(&self, config: &ServerConfig) -> ServerConfig {
+    let mut server = config.clone();
+    if self.bind.is_some() || self.port.is_some() {
+      server.unix_socket = None;
+      server.host = self.bind.clone().or(server.host);
+      server.port = self.port.or(server.port);
+    }
+    if let Some(socket) = &self.unix_socket {
+      server.unix_socket = Some(socket.clone());
+      server.tls = None;
+    }
+    server
+  }
+}
+
+#[derive(Args, Debug)]
+pub struct IndexArgs {
+  /// Print the statistics as json
+  #[arg(long)]
+  pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+  /// Output file, `-` for stdout, or output folder of the html export
+  #[arg(short, long, default_value = "-")]
+  pub output: String,
+
+  /// Export format
+  #[arg(short, long, value_enum, default_value_t = ExportFormat::Json)]
+  pub format: ExportFormat,
+
+  /// Feed of the notes having this tag
+  #[arg(long)]
+  pub tag: Option<String>,
+
+  /// Feed of the notes of this folder, relative to the vault root
+  #[arg(long)]
+  pub folder: Option<String>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+  /// The notes as a json array
+  Json,
+  /// A static site, one html page per note
+  Html,
+  /// RSS 2.0 feed of the recent public notes
+  Rss,
+  /// Atom feed of the recent public notes
+  Atom,
+  /// JSON Feed of the recent public notes
+  JsonFeed,
+}
+
+impl ExportFormat {
+  fn feed_kind(&self) -> Option<FeedKind> {
+    match self {
+      ExportFormat::Rss => Some(FeedKind::Rss),
+      ExportFormat::Atom => Some(FeedKind::Atom),
+      ExportFormat::JsonFeed => Some(FeedKind::Json),
+      ExportFormat::Json | ExportFormat::Html => None,
+    }
+  }
+}
+
+#[derive(Args, Debug)]
+pub struct QueryArgs {
+  /// Notes having all of these tags
+  #[arg(short, long = "tag")]
+  pub tags: Vec<String>,
+
+  /// Notes of this type
+  #[arg(long = "type")]
+  pub r#type: Option<String>,
+
+  /// Only public notes
+  #[arg(long, conflicts_with = "private")]
+  pub public: bool,
+
+  /// Only private notes
+  #[arg(long)]
+  pub private: bool,
+
+  /// Notes updated or created at or after this date
+  #[arg(long)]
+  pub after: Option<String>,
+
+  /// Notes updated or created at or before this date
+  #[arg(long)]
+  pub before: Option<String>,
+
+  /// Order of the notes
+  #[arg(long, value_enum, default_value_t = NoteSort::Path)]
+  pub sort: NoteSort,
+
+  /// Text to search in the name, title and content
+  pub text: Option<String>,
+
+  /// Print the notes as json
+  #[arg(long)]
+  pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigArgs {
+  #[command(subcommand)]
+  pub action: ConfigAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+  /// Print the effective configuration, with defaults and overrides applied
+  Print,
+}
+
+#[derive(Serialize)]
+struct IndexStats {
+  notes: usize,
+  public: usize,
+  private: usize,
+  links: usize,
+  broken_links: usize,
+  duration_ms: u128,
+}
+
+/// Messages of this crate, printed as they are: the warnings and errors on stderr
+struct Logger;
+
+impl Log for Logger {
+  fn enabled(&self, metadata: &log::Metadata) -> bool {
+    metadata.level() <= log::max_level() && metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+  }
+
+  fn log(&self, record: &Record) {
+    if !self.enabled(record.metadata()) {
+      return;
+    }
+    match record.level() {
+      Level::Info => println!("{}", record.args()),
+      _ => eprintln!("{}", record.args()),
+    }
+  }
+
+  fn flush(&self) {}
+}
+
+/// Run the `rust-md` command line with the arguments of the process
+pub async fn run() {
+  let cli = Cli::parse();
+
+  if log::set_logger(&Logger).is_ok() {
+    log::set_max_level(if cli.verbose {
+      LevelFilter::Debug
+    } else {
+      LevelFilter::Info
+    });
+  }
+
+  // Relative to the current directory, unlike the root path of the config file
+  let root_override = cli.root.as_ref().map(|root| {
+    validation::resolve_path(root, Path::new(""))
+      .to_string_lossy()
+      .to_string()
+  });
+
+  let config = match load_config(&cli.config, root_override.as_deref()) {
+    Ok(config) => config,
+    Err(err) => {
+      eprintln!("{}\n{}", "Invalid config:".red().bold(), err);
+      process::exit(1);
+    }
+  };
+
+  let command = cli.command.unwrap_or(Command::Serve(ServeArgs::default()));
+  if let Command::Serve(args) = command {
+    serve(config, args, &cli.config, root_override).await;
+    return;
+  }
+
+  // Other commands work on a single vault
+  let Some((_, vault_config)) = config
+    .vault_configs()
+    .into_iter()
+    .find(|(name, _)| *name == cli.vault)
+  else {
+    eprintln!("{} Unknown vault '{}'", "Error:".red().bold(), cli.vault);
+    process::exit(1);
+  };
+
+  let result = match command {
+    Command::Serve(_) => unreachable!(),
+    Command::Index(args) => index(&vault_config, args).await,
+    Command::Check => check(&vault_config).await.map(|errors| {
+      if errors > 0 {
+        process::exit(1);
+      }
+    }),
+    Command::Export(args) => export(&vault_config, args).await,
+    Command::Query(args) => query(&vault_config, args).await,
+    Command::Config(args) => self::config(&config, args),
+  };
+
+  if let Err(err) = result {
+    eprintln!("{} {}", "Error:".red().bold(), err);
+    process::exit(1);
+  }
+}
+
+/// ! NEED TO REWRITE EVERYTHING AND THINK OF THE LOGIC BEFORE....
+async fn serve(
+  config: UserConfig,
+  args: ServeArgs,
+  config_path: &str,
+  root_override: Option<String>,
+) {
+  // ! DEBUG
+  let app_start_time = std::time::Instant::now();
+
+  println!(
+    "{}{}Starting...\n",
+    " rust-md ".on_purple().bold(),
+    " v0.1 ".yellow().bold()
+  );
+
+  let server = args.server_config(&config.server);
+
+  let mut vaults = Vec::new();
+  for (name, vault_config) in config.vault_configs() {
+    let vault = match Vault::open(&name, vault_config).await {
+      Ok(vault) => vault,
+      Err(err) => {
+        eprintln!("{}", err);
+        process::exit(1);
+      }
+    };
+
+    // Start watching files for changes in a separate task
+    let watched = vault.clone();
+    tokio::spawn(async move {
+      if let Err(e) = watched.watch().await {
+        eprintln!("Error while watching files: {}", e);
+      }
+    });
+
+    vaults.push(vault);
+  }
+
+  // Apply config changes without restarting
+  let config_path = config_path.to_string();
+  let reload_vaults = vaults.clone();
+  tokio::spawn(async move {
+    if let Err(e) = reload::watch_config(&config_path, root_override, config, reload_vaults).await {
+      eprintln!("Error while watching the config file: {}", e);
+    }
+  });
+
+  println!("{}", "Starting webserver...".yellow().bold());
+
+  let app = app::create(vaults).await;
+
+  let listener = match Listener::bind(&server).await {
+    Ok(listener) => listener,
+    Err(err) => {
+      eprintln!("{:#}", err);
+      process::exit(1);
+    }
+  };
+
+  println!(
+    "\n{} {}",
+    " rust-md ".on_bright_green().bold(),
+    format!("Ready in: {:?}", app_start_time.elapsed())
+      .green()
+      .bold()
+  );
+  println!("\n➜  Local: {}\n", listener.url().cyan());
+
+  if let Err(err) = listener.serve(app).await {
+    eprintln!("{:#}", err);
+    process::exit(1);
+  }
+}
+
+/// Index the whole vault in memory
+pub async fn load_notes(config: &UserConfig) -> Result<HashMap<String, Note>, String> {
+  let notes = Arc::new(Mutex::new(HashMap::new()));
+  find_all_notes(&PathBuf::from(&config.root_path), notes.clone(), config).await?;
+
+  let mut notes = notes.lock().await.clone();
+  // The ids of the cache are reused, the cache itself is written by the server
+  IdCache::load(config).assign(&mut notes);
+  Ok(notes)
+}
+
+pub async fn index(config: &UserConfig, args: IndexArgs) -> Result<(), String> {
+  let start = std::time::Instant::now();
+  let notes = load_notes(config).await?;
+
+  let public = notes.values().filter(|note| note.public).count();
+  let links: Vec<_> = notes.values().flat_map(|note| &note.data.links).collect();
+  let stats = IndexStats {
+    notes: notes.len(),
+    public,
+    private: notes.len() - public,
+    links: links.len(),
+    broken_links: links
+      .iter()
+      .filter(|link| !Path::new(&link.target_path).is_file())
+      .count(),
+    duration_ms: start.elapsed().as_millis(),
+  };
+
+  if args.json {
+    println!("{}", serde_json::to_string_pretty(&stats).unwrap());
+  } else {
+    println!("{}", "Index".yellow().bold());
+    println!("  notes:        {}", stats.notes.to_string().bold());
+    println!("  public:       {}", stats.public.to_string().bold());
+    println!("  private:      {}", stats.private.to_string().bold());
+    println!("  links:        {}", stats.links.to_string().bold());
+    println!("  broken links: {}", stats.broken_links.to_string().bold());
+    println!(
+      "  indexed in:   {}",
+      format!("{:?}", start.elapsed()).bold()
+    );
+  }
+
+  Ok(())
+}
+
+/// Returns the number of errors found
+pub async fn check(config: &UserConfig) -> Result<usize, String> {
+  let notes = load_notes(config).await?;
+
+  let mut paths: Vec<_> = notes.keys().collect();
+  paths.sort();
+
+  let mut errors = 0;
+  let mut warnings = 0;
+  for path in paths {
+    let note = &notes[path];
+    for link in &note.data.links {
+      if !Path::new(&link.target_path).is_file() {
+        errors += 1;
+        println!(
+          "{} {}: broken link to {}",
+          "error".red().bold(),
+          path.bold(),
+          link.target_path
+        );
+      } else if note.public && !link.target_public.unwrap_or(false) {
+        warnings += 1;
+        println!(
+          "{} {}: public note links to private note {}",
+          "warning".yellow().bold(),
+          path.bold(),
+          link.target_path
+        );
+      }
+    }
+    for violation in &note.data.typed.violations {
+      errors += 1;
+      println!(
+        "{} {}: {}: {} (type {})",
+        "error".red().bold(),
+        path.bold(),
+        violation.field,
+        violation.message,
+        note.data.metadata.r#type.as_deref().unwrap_or_default()
+      );
+    }
+    for invalid in &note.data.dates.invalid {
+      warnings += 1;
+      println!(
+        "{} {}: unrecognised date {}, see dates.formats",
+        "warning".yellow().bold(),
+        path.bold(),
+        invalid
+      );
+    }
+  }
+
+  for (slug, paths) in slug::collisions(&notes) {
+    warnings += 1;
+    println!(
+      "{} {}: same url as {}",
+      "warning".yellow().bold(),
+      paths[0].bold(),
+      paths[1..].join(", ")
+    );
+    println!(
+      "  {} only the first one is reached at {}",
+      "·".bright_black(),
+      slug
+    );
+  }
+
+  println!(
+    "\n{} error(s), {} warning(s) in {} note(s)",
+    errors,
+    warnings,
+    notes.len()
+  );
+
+  Ok(errors)
+}
+
+pub async fn export(config: &UserConfig, args: ExportArgs) -> Result<(), String> {
+  if args.format == ExportFormat::Html && args.output == "-" {
+    return Err("The html export needs an output folder, ex: --output ./site".to_string());
+  }
+
+  let notes = load_notes(config).await?;
+
+  if args.format == ExportFormat::Html {
+    let stats = site::export_site(config, &notes, Path::new(&args.output))?;
+    eprintln!(
+      "{} {} notes, {} assets, {} folder and {} tag pages, {} feeds to {}",
+      "Exported".green().bold(),
+      stats.notes,
+      stats.assets,
+      stats.folders,
+      stats.tags,
+      stats.feeds,
+      args.output.bold()
+    );
+    return Ok(());
+  }
+
+  if let Some(kind) = args.format.feed_kind() {
+    let filter = FeedFilter {
+      tag: args.tag,
+      folder: args.folder,
+    };
+    let (_, feed) = site::static_feed(config, &notes, kind, &filter)?;
+    if args.output == "-" {
+      println!("{}", feed);
+    } else {
+      std::fs::write(&args.output, feed)
+        .map_err(|err| format!("Failed to write '{}': {}", args.output, err))?;
+      eprintln!(
+        "{} feed to {}",
+        "Exported".green().bold(),
+        args.output.bold()
+      );
+    }
+    return Ok(());
+  }
+
+  let mut visible: Vec<_> = notes
+    .into_values()
+    .filter(|note| note.public || config.private.include)
+    .collect();
+  visible.sort_by(|a, b| a.path.cmp(&b.path));
+
+  let json = serde_json::to_string_pretty(&visible).map_err(|err| err.to_string())?;
+  if args.output == "-" {
+    println!("{}", json);
+  } else {
+    std::fs::write(&args.output, json)
+      .map_err(|err| format!("Failed to write '{}': {}", args.output, err))?;
+    eprintln!(
+      "{} {} notes to {}",
+      "Exported".green().bold(),
+      visible.len(),
+      args.output.bold()
+    );
+  }
+
+  Ok(())
+}
+
+pub async fn query(config: &UserConfig, args: QueryArgs) -> Result<(), String> {
+  let parse_arg = |value: &Option<String>| {
+    value
+      .as_ref()
+      .map(|value| {
+        dates::parse_date(value, &config.dates.formats)
+          .ok_or_else(|| format!("Unrecognised date '{}', see dates.formats", value))
+      })
+      .transpose()
+  };
+  let after = parse_arg(&args.after)?;
+  let before = parse_arg(&args.before)?;
+
+  let notes = load_notes(config).await?;
+  let filter = NoteFilter {
+    tags: args.tags,
+    r#type: args.r#type,
+    public: match (args.public, args.private) {
+      (true, _) => Some(true),
+      (_, true) => Some(false),
+      _ => None,
+    },
+    after,
+    before,
+    text: args.text,
+  };
+  let found = search::search(notes.values(), &filter, args.sort);
+
+  if args.json {
+    println!("{}", serde_json::to_string_pretty(&found).unwrap());
+    return Ok(());
+  }
+
+  for note in &found {
+    println!(
+      "{} {}{}",
+      if note.public {
+        "public ".green()
+      } else {
+        "private".red()
+      },
+      note.path.bold(),
+      note
+        .data
+        .metadata
+        .title
+        .as_ref()
+        .map(|title| format!(" - {}", title))
+        .unwrap_or_default()
+    );
+  }
+  eprintln!("\n{} note(s)", found.len());
+
+  Ok(())
+}
+
+pub fn config(config: &UserConfig, args: ConfigArgs) -> Result<(), String> {
+  match args.action {
+    ConfigAction::Print => {
+      let yaml = serde_yaml_ng::to_string(config).map_err(|err| err.to_string())?;
+      print!("{}", yaml);
+    }
+  }
+
+  Ok(())
+}
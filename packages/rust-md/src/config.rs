@@ -1,28 +1,659 @@
+use crate::validation::{self, ConfigErrors};
+use colored::Colorize;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
 pub struct PrivateConfig {
   pub include: bool,
   pub icon: String,
+  /// Ordered visibility rules, the first matching rule wins
+  pub rules: Vec<VisibilityRule>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl Default for PrivateConfig {
+  fn default() -> Self {
+    Self {
+      include: false,
+      icon: "🔒".to_string(),
+      rules: Vec::new(),
+    }
+  }
+}
+
+/// A note matching every condition of the rule gets its visibility
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct VisibilityRule {
+  pub name: Option<String>,
+  /// Glob over the path relative to the vault root, ex: `blog/**`
+  pub path: Option<String>,
+  /// Matches when the note has any of these tags
+  pub tags: Option<Vec<String>>,
+  pub r#type: Option<String>,
+  /// Frontmatter fields and their expected values
+  #[serde(default)]
+  pub fields: BTreeMap<String, serde_yaml_ng::Value>,
+  pub public: bool,
+}
+
+/// How raw html written in the markdown is handled
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RawHtml {
+  /// Keep raw html (still sanitised when enabled)
+  #[default]
+  Keep,
+  /// Remove raw html from public notes only
+  StripPublic,
+  /// Remove raw html from every note
+  Strip,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SanitizeConfig {
+  pub enabled: bool,
+  pub raw_html: RawHtml,
+  /// Allowed tags, `None` uses the sanitizer defaults
+  pub tags: Option<BTreeSet<String>>,
+  /// Allowed attributes per tag, `None` uses the sanitizer defaults
+  pub attributes: Option<BTreeMap<String, BTreeSet<String>>>,
+  pub url_schemes: BTreeSet<String>,
+  /// `rel` attribute added to external links
+  pub link_rel: Option<String>,
+}
+
+impl Default for SanitizeConfig {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      raw_html: RawHtml::Keep,
+      tags: None,
+      attributes: None,
+      url_schemes: ["http", "https", "mailto"]
+        .into_iter()
+        .map(String::from)
+        .collect(),
+      link_rel: Some("noopener nofollow".to_string()),
+    }
+  }
+}
+
+/// How the url of a note is built from its path
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SlugStyle {
+  /// The file names as they are, ex: `/files/Blog/My Note`
+  #[default]
+  Preserve,
+  /// Lowercase words separated by `-`, ex: `/files/blog/my-note`
+  Kebab,
+  /// Kebab case transliterated to ASCII, ex: `Café` is `cafe`
+  Ascii,
+}
+
+/// What the feeds contain for each note
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedContent {
+  /// The html of the note, along with its summary
+  #[default]
+  Full,
+  /// The `summary` of the frontmatter only
+  Summary,
+}
+
+/// Settings of the published site, shared by every vault
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteConfig {
+  /// Absolute url the site is published at, ex: `https://notes.example.com`.
+  /// The server falls back to its `Host` header, the export requires it for the feeds and the sitemap.
+  pub base_url: Option<String>,
+  pub title: Option<String>,
+  /// Number of notes in the feeds
+  pub feed_limit: usize,
+  pub feed_content: FeedContent,
+}
+
+impl Default for SiteConfig {
+  fn default() -> Self {
+    Self {
+      base_url: None,
+      title: None,
+      feed_limit: 20,
+      feed_content: FeedContent::Full,
+    }
+  }
+}
+
+/// Certificate chain and private key of the https server, pem files relative to the config file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+  pub cert: String,
+  pub key: String,
+}
+
+/// Where the webserver listens, the options of `serve` take precedence
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+  /// Ip address or host name, ex: `0.0.0.0` or `::` for every IPv4 or IPv6 address
+  pub host: Option<String>,
+  pub port: Option<u16>,
+  /// Unix domain socket used instead of the host and port, relative to the config file
+  pub unix_socket: Option<String>,
+  /// Serve https, the certificate is reloaded when its files change
+  pub tls: Option<TlsConfig>,
+}
+
+/// What a token allows, each scope includes the ones it implies
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scope {
+  /// Public notes, their pages, tags, feeds and assets
+  ReadPublic,
+  /// Private notes and the private sections of every note, implies `read-public`
+  ReadPrivate,
+  /// Changes to the vault, implies `read-public`. No endpoint uses it yet
+  Write,
+  /// Every scope, along with the endpoints explaining the config like `/visibility`
+  Admin,
+}
+
+impl Scope {
+  /// Whether a token with this scope is allowed what `scope` allows
+  pub fn allows(self, scope: Scope) -> bool {
+    self == scope
+      || self == Scope::Admin
+      || (scope == Scope::ReadPublic && matches!(self, Scope::ReadPrivate | Scope::Write))
+  }
+}
+
+/// An api token, sent as `Authorization: Bearer {token}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TokenConfig {
+  /// Shown in the logs instead of the token
+  pub name: String,
+  #[serde(serialize_with = "redacted")]
+  pub token: String,
+  pub scopes: Vec<Scope>,
+}
+
+/// Tokens are never printed, ex: by `rust-md config` or when the config is reloaded
+fn redacted<S: serde::Serializer>(_: &str, serializer: S) -> Result<S::Ok, S::Error> {
+  serializer.serialize_str("********")
+}
+
+fn redacted_secret<S: serde::Serializer>(
+  secret: &Option<String>,
+  serializer: S,
+) -> Result<S::Ok, S::Error> {
+  redacted(secret.as_deref().unwrap_or_default(), serializer)
+}
+
+/// Access to the server, shared by every vault.
+/// Without any token every client reads what `private.include` allows,
+/// otherwise the scopes of the client decide whether it reads the private notes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+  pub tokens: Vec<TokenConfig>,
+  /// Yaml list of more tokens, relative to the config file.
+  /// Read again when the config file is reloaded.
+  pub token_file: Option<String>,
+  /// Tokens read from `token_file`
+  #[serde(skip)]
+  pub file_tokens: Vec<TokenConfig>,
+  /// Scopes of the requests without a token when tokens are defined
+  pub anonymous: Vec<Scope>,
+  /// Signs the share links, a secret is generated in the cache folder when missing.
+  /// Changing it invalidates every share link.
+  #[serde(
+    serialize_with = "redacted_secret",
+    skip_serializing_if = "Option::is_none"
+  )]
+  pub share_secret: Option<String>,
+}
+
+impl Default for AuthConfig {
+  fn default() -> Self {
+    Self {
+      tokens: Vec::new(),
+      token_file: None,
+      file_tokens: Vec::new(),
+      anonymous: vec![Scope::ReadPublic],
+      share_secret: None,
+    }
+  }
+}
+
+impl AuthConfig {
+  /// Tokens of the config file, then of the token file
+  pub fn tokens(&self) -> impl Iterator<Item = &TokenConfig> {
+    self.tokens.iter().chain(&self.file_tokens)
+  }
+
+  /// Whether requests are checked against the tokens
+  pub fn enabled(&self) -> bool {
+    self.tokens().next().is_some()
+  }
+}
+
+/// Where the dates of a note are taken from when its frontmatter has none
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DateFallback {
+  /// Creation and modification times of the file
+  File,
+  /// First and last commits of the file
+  Git,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatesConfig {
+  /// [chrono formats](https://docs.rs/chrono/latest/chrono/format/strftime/index.html)
+  /// of `created` and `updated`, tried in order after RFC 3339.
+  /// Dates without a timezone are in UTC.
+  pub formats: Vec<String>,
+  /// Tried in order for the missing dates
+  pub fallback: Vec<DateFallback>,
+}
+
+impl Default for DatesConfig {
+  fn default() -> Self {
+    Self {
+      formats: crate::dates::DEFAULT_FORMATS
+        .into_iter()
+        .map(String::from)
+        .collect(),
+      fallback: vec![DateFallback::File],
+    }
+  }
+}
+
+/// Type of a frontmatter field, values are coerced to it when possible
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+  String,
+  Integer,
+  Number,
+  Boolean,
+  /// Parsed with `dates.formats`, returned as RFC 3339
+  Date,
+  List,
+}
+
+impl FieldType {
+  pub fn name(&self) -> &'static str {
+    match self {
+      FieldType::String => "a string",
+      FieldType::Integer => "an integer",
+      FieldType::Number => "a number",
+      FieldType::Boolean => "a boolean",
+      FieldType::Date => "a date",
+      FieldType::List => "a list",
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FieldSchema {
+  pub r#type: FieldType,
+  #[serde(default)]
+  pub required: bool,
+  /// Allowed values, of each item for a list
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub r#enum: Option<Vec<serde_json::Value>>,
+  /// Value of the field when the note does not set it
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub default: Option<serde_json::Value>,
+}
+
+/// Frontmatter fields of the notes of one `type`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TypeSchema {
+  pub fields: BTreeMap<String, FieldSchema>,
+}
+
+/// An extra vault served under `/vaults/{name}`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct VaultConfig {
+  pub name: String,
+  pub root_path: String,
+  #[serde(default)]
+  pub private: PrivateConfig,
+  #[serde(default)]
+  pub ignore: Vec<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub templates: Option<String>,
+}
+
+/// Name of the vault defined by the top level fields
+pub const DEFAULT_VAULT: &str = "default";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct UserConfig {
+  /// Absolute once loaded, relative paths are resolved against the config file
   pub root_path: String,
+  #[serde(default)]
   pub private: PrivateConfig,
+  #[serde(default)]
   pub ignore: Vec<String>,
+  #[serde(default)]
+  pub sanitize: SanitizeConfig,
+  #[serde(default)]
+  pub site: SiteConfig,
+  #[serde(default)]
+  pub dates: DatesConfig,
+  #[serde(default)]
+  pub slug_style: SlugStyle,
+  #[serde(default)]
+  pub auth: AuthConfig,
+  #[serde(default)]
+  pub server: ServerConfig,
+  /// Folder of the files kept between runs, like the note ids.
+  /// Relative to the config file, `.rust-md` next to it by default.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub cache_dir: Option<String>,
+  /// Schemas of the frontmatter per note `type`
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub schemas: BTreeMap<String, TypeSchema>,
+  /// Folder of the html page templates, relative to the vault root.
+  /// Notes are rendered as html pages only when it is set.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub templates: Option<String>,
+  #[serde(default)]
+  pub vaults: Vec<VaultConfig>,
+}
+
+impl UserConfig {
+  /// Find a vault by name, the top level fields being the default vault
+  pub fn vault(&self, name: &str) -> Option<VaultConfig> {
+    if let Some(vault) = self.vaults.iter().find(|vault| vault.name == name) {
+      return Some(vault.clone());
+    }
+    (name == DEFAULT_VAULT).then(|| VaultConfig {
+      name: DEFAULT_VAULT.to_string(),
+      root_path: self.root_path.clone(),
+      private: self.private.clone(),
+      ignore: self.ignore.clone(),
+      templates: self.templates.clone(),
+    })
+  }
+
+  /// Config of every vault, starting with the default one.
+  ///
+  /// Each config has the root, privacy and ignore settings of its vault, the shared settings,
+  /// and the list of all vaults to resolve `vault:` links.
+  pub fn vault_configs(&self) -> Vec<(String, UserConfig)> {
+    let mut vaults = vec![self.vault(DEFAULT_VAULT).unwrap()];
+    vaults.extend(self.vaults.iter().cloned());
+
+    vaults
+      .iter()
+      .map(|vault| {
+        (
+          vault.name.clone(),
+          UserConfig {
+            root_path: vault.root_path.clone(),
+            private: vault.private.clone(),
+            ignore: vault.ignore.clone(),
+            sanitize: self.sanitize.clone(),
+            site: self.site.clone(),
+            dates: self.dates.clone(),
+            slug_style: self.slug_style,
+            auth: self.auth.clone(),
+            server: self.server.clone(),
+            schemas: self.schemas.clone(),
+            cache_dir: self.cache_dir.clone(),
+            templates: vault.templates.clone(),
+            vaults: vaults.clone(),
+          },
+        )
+      })
+      .collect()
+  }
+}
+
+/// Prefix of the environment variables overriding the config,
+/// nested fields are separated by `__`, ex: `RUST_MD_PRIVATE__INCLUDE=false`
+pub const ENV_PREFIX: &str = "RUST_MD_";
+
+/// Fields holding a list of strings, the value of their variable can be comma separated
+const ENV_LIST_FIELDS: [&str; 6] = [
+  "ignore",
+  "sanitize.tags",
+  "sanitize.url_schemes",
+  "dates.formats",
+  "dates.fallback",
+  "auth.anonymous",
+];
+
+/// Load, validate and resolve the config file, every problem is reported at once.
+///
+/// `root_override` replaces the root path of the file, ex: from the cli.
+pub fn load_config(path: &str, root_override: Option<&str>) -> Result<UserConfig, ConfigErrors> {
+  let source = std::fs::read_to_string(path)
+    .map_err(|err| ConfigErrors::single(path, None, format!("Failed to read the file: {}", err)))?;
+
+  if source.trim().is_empty() {
+    return Err(ConfigErrors::single(
+      path,
+      None,
+      "The config file is empty.",
+    ));
+  }
+
+  let mut value: serde_yaml_ng::Value = serde_yaml_ng::from_str(&source).map_err(|err| {
+    ConfigErrors::single(
+      path,
+      err.location().map(|location| location.line()),
+      err.to_string(),
+    )
+  })?;
+  apply_env_overrides(&mut value, std::env::vars());
+  if let (Some(root), Some(fields)) = (root_override, value.as_mapping_mut()) {
+    fields.insert("root_path".into(), root.into());
+  }
+
+  validation::validate(path, &source, value)
 }
 
-pub fn load_config(path: &str) -> Result<UserConfig, Box<dyn std::error::Error>> {
-  let file = std::fs::File::open(path)?;
-  let file_size = file.metadata()?.len();
+/// Default config of a vault opened without a config file, ex: by the desktop app.
+/// The cache is kept in the `.rust-md` folder of the vault.
+pub fn folder_config(root_path: &str) -> Result<UserConfig, ConfigErrors> {
+  let path = Path::new(root_path).join("config.yaml");
+  let mut fields = serde_yaml_ng::Mapping::new();
+  fields.insert("root_path".into(), root_path.into());
+  validation::validate(
+    &path.to_string_lossy(),
+    "",
+    serde_yaml_ng::Value::Mapping(fields),
+  )
+}
+
+/// List the fields that differ between two configs, ex: `private.include: true -> false`
+pub fn diff(old: &UserConfig, new: &UserConfig) -> Vec<String> {
+  let old = serde_yaml_ng::to_value(old).unwrap_or_default();
+  let new = serde_yaml_ng::to_value(new).unwrap_or_default();
+
+  let mut changes = Vec::new();
+  diff_values("", &old, &new, &mut changes);
+  changes
+}
+
+fn diff_values(
+  path: &str,
+  old: &serde_yaml_ng::Value,
+  new: &serde_yaml_ng::Value,
+  changes: &mut Vec<String>,
+) {
+  use serde_yaml_ng::Value;
 
-  if file_size == 0 {
-    return Err("The config file is empty.".into());
+  match (old, new) {
+    (Value::Mapping(old_fields), Value::Mapping(new_fields)) => {
+      let keys = old_fields.keys().chain(
+        new_fields
+          .keys()
+          .filter(|key| !old_fields.contains_key(*key)),
+      );
+      for key in keys {
+        let field = key
+          .as_str()
+          .map(String::from)
+          .unwrap_or_else(|| format!("{:?}", key));
+        let field_path = if path.is_empty() {
+          field
+        } else {
+          format!("{}.{}", path, field)
+        };
+        diff_values(
+          &field_path,
+          old_fields.get(key).unwrap_or(&Value::Null),
+          new_fields.get(key).unwrap_or(&Value::Null),
+          changes,
+        );
+      }
+    }
+    // Vaults keep their order, so their items can be compared one by one
+    (Value::Sequence(old_items), Value::Sequence(new_items))
+      if old_items.len() == new_items.len()
+        && old_items.iter().all(Value::is_mapping)
+        && new_items.iter().all(Value::is_mapping) =>
+    {
+      for (index, (old_item, new_item)) in old_items.iter().zip(new_items).enumerate() {
+        diff_values(&format!("{}[{}]", path, index), old_item, new_item, changes);
+      }
+    }
+    _ if old != new => changes.push(format!(
+      "{}: {} -> {}",
+      path,
+      serde_json::to_string(old).unwrap_or_default(),
+      serde_json::to_string(new).unwrap_or_default()
+    )),
+    _ => {}
   }
+}
 
-  let reader = std::io::BufReader::new(file);
+/// Override config fields with `RUST_MD_*` environment variables.
+///
+/// Values are parsed as yaml, strings are kept as is and a comma separated
+/// value can be used for the list fields, ex: `RUST_MD_IGNORE=.obsidian,templates`
+fn apply_env_overrides(
+  config: &mut serde_yaml_ng::Value,
+  vars: impl Iterator<Item = (String, String)>,
+) {
+  use serde_yaml_ng::Value;
 
-  let config: UserConfig = serde_yaml_ng::from_reader(reader)?;
-  Ok(config)
+  for (key, raw) in vars {
+    let Some(field_path) = key.strip_prefix(ENV_PREFIX) else {
+      continue;
+    };
+    // Used by the cli, not a config field
+    if field_path == "CONFIG" {
+      continue;
+    }
+
+    let mut fields: Vec<String> = field_path
+      .split("__")
+      .map(|field| field.to_lowercase())
+      .collect();
+    // Other tools may use the same prefix, only the config fields are overridden
+    if !validation::FIELDS.contains(&fields[0].as_str()) {
+      log::warn!(
+        "{} × Ignoring {}, `{}` is not a config field",
+        "[config]".yellow(),
+        key.bold(),
+        fields[0]
+      );
+      continue;
+    }
+    let is_list = ENV_LIST_FIELDS.contains(&fields.join(".").as_str());
+    let Some(last_field) = fields.pop() else {
+      continue;
+    };
+
+    let mut target = &mut *config;
+    for field in fields {
+      if !target.is_mapping() {
+        *target = Value::Mapping(Default::default());
+      }
+      target = target
+        .as_mapping_mut()
+        .unwrap()
+        .entry(Value::String(field))
+        .or_insert(Value::Null);
+    }
+    if !target.is_mapping() {
+      *target = Value::Mapping(Default::default());
+    }
+    let mapping = target.as_mapping_mut().unwrap();
+
+    let parsed = serde_yaml_ng::from_str::<Value>(&raw).unwrap_or(Value::String(raw.clone()));
+    let value = match mapping.get(last_field.as_str()) {
+      _ if is_list && !parsed.is_sequence() => Value::Sequence(
+        raw
+          .split(',')
+          .map(|item| Value::String(item.trim().to_string()))
+          .filter(|item| item.as_str() != Some(""))
+          .collect(),
+      ),
+      Some(Value::String(_)) => Value::String(raw),
+      _ => parsed,
+    };
+    mapping.insert(Value::String(last_field), value);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{apply_env_overrides, ENV_PREFIX};
+  use crate::validation;
+  use serde_yaml_ng::Value;
+
+  fn vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+    vars
+      .iter()
+      .map(|(key, value)| (format!("{}{}", ENV_PREFIX, key), value.to_string()))
+      .collect::<Vec<_>>()
+      .into_iter()
+  }
+
+  #[test]
+  fn list_overrides_of_fields_missing_from_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = format!("root_path: {}\n", dir.path().display());
+    let mut value: Value = serde_yaml_ng::from_str(&source).unwrap();
+
+    apply_env_overrides(
+      &mut value,
+      vars(&[
+        ("IGNORE", ".obsidian, templates"),
+        ("SANITIZE__URL_SCHEMES", "https,mailto"),
+        ("SITE__TITLE", "1,2"),
+        ("LOG", "debug"),
+      ]),
+    );
+    let path = dir.path().join("config.yaml");
+    let config = validation::validate(&path.to_string_lossy(), &source, value).unwrap();
+
+    assert_eq!(config.ignore, [".obsidian", "templates"]);
+    assert_eq!(
+      config.sanitize.url_schemes.into_iter().collect::<Vec<_>>(),
+      ["https", "mailto"]
+    );
+    // Not a list, the value is kept whole
+    assert_eq!(config.site.title.as_deref(), Some("1,2"));
+  }
 }
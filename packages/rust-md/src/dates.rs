@@ -0,0 +1,173 @@
+use crate::{
+  config::{DateFallback, DatesConfig},
+  parser::Metadata,
+};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, process::Command, time::SystemTime};
+
+/// Formats of the frontmatter dates, besides RFC 3339: ISO 8601 without timezone,
+/// the Obsidian date and datetime properties and `2021-12-09-22:23`
+pub const DEFAULT_FORMATS: [&str; 6] = [
+  "%Y-%m-%dT%H:%M:%S",
+  "%Y-%m-%dT%H:%M",
+  "%Y-%m-%d %H:%M:%S",
+  "%Y-%m-%d %H:%M",
+  "%Y-%m-%d-%H:%M",
+  "%Y-%m-%d",
+];
+
+/// Where a date of a note comes from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DateSource {
+  Frontmatter,
+  File,
+  Git,
+}
+
+/// Normalised dates of a note, the raw values stay in its metadata
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NoteDates {
+  pub created: Option<DateTime<FixedOffset>>,
+  pub created_source: Option<DateSource>,
+  pub updated: Option<DateTime<FixedOffset>>,
+  pub updated_source: Option<DateSource>,
+  /// Frontmatter dates that match none of the formats, ex: `created: someday`
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub invalid: Vec<String>,
+}
+
+impl NoteDates {
+  /// Last update, else creation
+  pub fn latest(&self) -> Option<DateTime<FixedOffset>> {
+    self.updated.or(self.created)
+  }
+}
+
+/// Parse a date with RFC 3339 or one of the formats, dates without a timezone are in UTC
+pub fn parse_date(value: &str, formats: &[String]) -> Option<DateTime<FixedOffset>> {
+  let value = value.trim();
+
+  if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+    return Some(date);
+  }
+  for format in formats {
+    if let Ok(date) = DateTime::parse_from_str(value, format) {
+      return Some(date);
+    }
+    if let Ok(date) = NaiveDateTime::parse_from_str(value, format) {
+      return Some(date.and_utc().fixed_offset());
+    }
+    if let Some(date) = NaiveDate::parse_from_str(value, format)
+      .ok()
+      .and_then(|date| date.and_hms_opt(0, 0, 0))
+    {
+      return Some(date.and_utc().fixed_offset());
+    }
+  }
+  None
+}
+
+/// Dates of a note from its frontmatter, the missing ones from the fallbacks.
+///
+/// `file_path` is the absolute path of the note.
+pub fn note_dates(file_path: &Path, metadata: &Metadata, config: &DatesConfig) -> NoteDates {
+  let mut dates = NoteDates::default();
+
+  for (field, value, date, source) in [
+    (
+      "created",
+      &metadata.created,
+      &mut dates.created,
+      &mut dates.created_source,
+    ),
+    (
+      "updated",
+      &metadata.updated,
+      &mut dates.updated,
+      &mut dates.updated_source,
+    ),
+  ] {
+    let Some(value) = value else {
+      continue;
+    };
+    match parse_date(value, &config.formats) {
+      Some(parsed) => {
+        *date = Some(parsed);
+        *source = Some(DateSource::Frontmatter);
+      }
+      None => dates.invalid.push(format!("{}: {}", field, value)),
+    }
+  }
+
+  for fallback in &config.fallback {
+    if dates.created.is_some() && dates.updated.is_some() {
+      break;
+    }
+    let (created, updated, source) = match fallback {
+      DateFallback::File => {
+        let (created, updated) = file_dates(file_path);
+        (created, updated, DateSource::File)
+      }
+      DateFallback::Git => {
+        let (created, updated) = git_dates(file_path);
+        (created, updated, DateSource::Git)
+      }
+    };
+    if dates.created.is_none() && created.is_some() {
+      dates.created = created;
+      dates.created_source = Some(source);
+    }
+    if dates.updated.is_none() && updated.is_some() {
+      dates.updated = updated;
+      dates.updated_source = Some(source);
+    }
+  }
+
+  dates
+}
+
+/// Creation and modification times, the creation time is not available on every file system
+fn file_dates(file_path: &Path) -> (Option<DateTime<FixedOffset>>, Option<DateTime<FixedOffset>>) {
+  let Ok(metadata) = fs::metadata(file_path) else {
+    return (None, None);
+  };
+  let to_date = |time: SystemTime| DateTime::<Utc>::from(time).fixed_offset();
+  (
+    metadata.created().ok().map(to_date),
+    metadata.modified().ok().map(to_date),
+  )
+}
+
+/// Dates of the first and last commits of the file, `None` outside of a git repository
+fn git_dates(file_path: &Path) -> (Option<DateTime<FixedOffset>>, Option<DateTime<FixedOffset>>) {
+  let (Some(folder), Some(file_name)) = (file_path.parent(), file_path.file_name()) else {
+    return (None, None);
+  };
+  let Ok(output) = Command::new("git")
+    .args(["log", "--follow", "--format=%aI", "--"])
+    .arg(file_name)
+    .current_dir(folder)
+    .output()
+  else {
+    return (None, None);
+  };
+  if !output.status.success() {
+    return (None, None);
+  }
+
+  // Most recent commit first
+  let log = String::from_utf8_lossy(&output.stdout);
+  let mut commits = log
+    .lines()
+    .filter_map(|line| DateTime::parse_from_rfc3339(line.trim()).ok());
+  let updated = commits.next();
+  let created = commits.next_back().or(updated);
+  (created, updated)
+}
+
+/// The current date, for the documents generated without any dated note
+pub fn now() -> DateTime<FixedOffset> {
+  Utc::now().fixed_offset()
+}
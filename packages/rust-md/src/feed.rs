@@ -0,0 +1,398 @@
+use crate::{
+  app::Note,
+  config::{FeedContent, UserConfig},
+  dates, pages,
+  parser::{self, LinkStyle},
+};
+use chrono::{DateTime, FixedOffset};
+use pulldown_cmark_escape::escape_html;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path};
+
+/// Format of a feed
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedKind {
+  Rss,
+  Atom,
+  /// <https://www.jsonfeed.org/version/1.1/>
+  Json,
+}
+
+impl FeedKind {
+  pub fn content_type(&self) -> &'static str {
+    match self {
+      FeedKind::Rss => "application/rss+xml; charset=utf-8",
+      FeedKind::Atom => "application/atom+xml; charset=utf-8",
+      FeedKind::Json => "application/feed+json; charset=utf-8",
+    }
+  }
+
+  /// File name of the feed, in the static export and the server
+  pub fn file_name(&self) -> &'static str {
+    match self {
+      FeedKind::Rss => "feed.xml",
+      FeedKind::Atom => "atom.xml",
+      FeedKind::Json => "feed.json",
+    }
+  }
+}
+
+/// Restrict a feed to the notes of a tag or a folder
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct FeedFilter {
+  pub tag: Option<String>,
+  /// Folder relative to the vault root, with its sub folders
+  pub folder: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct FeedEntry {
+  pub title: String,
+  pub url: String,
+  pub summary: Option<String>,
+  /// Html content, in the full content mode
+  pub content: Option<String>,
+  pub published: Option<DateTime<FixedOffset>>,
+  pub updated: Option<DateTime<FixedOffset>>,
+  pub tags: Vec<String>,
+}
+
+impl FeedEntry {
+  fn date(&self) -> Option<DateTime<FixedOffset>> {
+    self.updated.or(self.published)
+  }
+}
+
+#[derive(Debug)]
+pub struct Feed {
+  pub title: String,
+  /// Page the feed is about
+  pub home_url: String,
+  pub feed_url: String,
+  pub entries: Vec<FeedEntry>,
+}
+
+/// Title of the feed, from the site title and the filter
+pub fn feed_title(config: &UserConfig, filter: &FeedFilter) -> String {
+  let title = config.site.title.as_deref().unwrap_or("rust-md");
+  match (&filter.tag, &filter.folder) {
+    (Some(tag), _) => format!("{} - #{}", title, tag),
+    (None, Some(folder)) => format!("{} - {}", title, folder.trim_matches('/')),
+    (None, None) => title.to_string(),
+  }
+}
+
+/// Most recently created or updated public notes matching the filter.
+///
+/// `url` gives the absolute url of a note, the links of the content
+/// are rendered with `link_style` then made absolute from it.
+pub fn feed_entries(
+  notes: &HashMap<String, Note>,
+  config: &UserConfig,
+  filter: &FeedFilter,
+  content: FeedContent,
+  link_style: LinkStyle,
+  url: impl Fn(&Note) -> String,
+) -> Vec<FeedEntry> {
+  let folder = filter
+    .folder
+    .as_ref()
+    .map(|folder| format!("{}/", folder.trim_matches('/')))
+    .filter(|folder| folder != "/");
+
+  let mut entries: Vec<(FeedEntry, &Note)> = notes
+    .values()
+    .filter(|note| note.public)
+    .filter(|note| {
+      filter.tag.as_ref().is_none_or(|tag| {
+        note
+          .data
+          .metadata
+          .tags
+          .as_ref()
+          .is_some_and(|tags| tags.contains(tag))
+      })
+    })
+    .filter(|note| {
+      folder
+        .as_ref()
+        .is_none_or(|folder| note.relative_file_path().starts_with(folder))
+    })
+    .map(|note| {
+      let metadata = &note.data.metadata;
+      let entry = FeedEntry {
+        title: pages::note_title(note),
+        url: url(note),
+        summary: metadata.summary.clone(),
+        content: None,
+        published: note.data.dates.created,
+        updated: note.data.dates.updated,
+        tags: metadata.tags.clone().unwrap_or_default(),
+      };
+      (entry, note)
+    })
+    .collect();
+
+  // Most recent first, notes without dates last
+  entries.sort_by(|(a, _), (b, _)| b.date().cmp(&a.date()).then_with(|| a.url.cmp(&b.url)));
+  entries.truncate(config.site.feed_limit);
+
+  entries
+    .into_iter()
+    .map(|(mut entry, note)| {
+      if content == FeedContent::Full {
+        entry.content = Some(absolute_urls(
+          &public_content(note, config, link_style),
+          &entry.url,
+        ));
+      }
+      entry
+    })
+    .collect()
+}
+
+/// Html of a note without its private sections, even when they are shown elsewhere
+fn public_content(note: &Note, config: &UserConfig, link_style: LinkStyle) -> String {
+  let mut public_config = config.clone();
+  public_config.private.include = false;
+  let path = Path::new(&config.root_path).join(note.relative_file_path());
+  let path = path.to_string_lossy().replace('\\', "/");
+  fs::read_to_string(&path)
+    .ok()
+    .and_then(|source| {
+      parser::render_markdown(&path, &note.name, &source, &public_config, link_style).ok()
+    })
+    .map(|(html, _, _)| html)
+    .unwrap_or_default()
+}
+
+/// Resolve the relative `href` and `src` of the html against `page_url`,
+/// feed readers show the content away from the page
+fn absolute_urls(html: &str, page_url: &str) -> String {
+  let origin_end = page_url
+    .find("://")
+    .and_then(|scheme| {
+      page_url[scheme + 3..]
+        .find('/')
+        .map(|path| scheme + 3 + path)
+    })
+    .unwrap_or(page_url.len());
+  let origin = &page_url[..origin_end];
+  let folder = &page_url[..page_url
+    .rfind('/')
+    .filter(|end| *end >= origin_end)
+    .unwrap_or(origin_end)];
+
+  let mut result = String::with_capacity(html.len());
+  let mut rest = html;
+  while let Some(start) = [" href=\"", " src=\""]
+    .iter()
+    .filter_map(|attribute| rest.find(attribute).map(|index| index + attribute.len()))
+    .min()
+  {
+    result.push_str(&rest[..start]);
+    rest = &rest[start..];
+    let end = rest.find('"').unwrap_or(rest.len());
+    let url = &rest[..end];
+
+    let is_absolute = url.starts_with('#')
+      || url.starts_with("//")
+      || url
+        .split_once(':')
+        .is_some_and(|(scheme, _)| !scheme.contains('/'));
+    if is_absolute {
+      result.push_str(url);
+    } else if url.starts_with('/') {
+      result.push_str(origin);
+      result.push_str(url);
+    } else {
+      let mut segments: Vec<&str> = folder[origin_end.min(folder.len())..]
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+      for segment in url.split('/') {
+        match segment {
+          "." => {}
+          ".." => {
+            segments.pop();
+          }
+          _ => segments.push(segment),
+        }
+      }
+      result.push_str(origin);
+      result.push('/');
+      result.push_str(&segments.join("/"));
+    }
+    rest = &rest[end..];
+  }
+  result.push_str(rest);
+  result
+}
+
+pub fn render(kind: FeedKind, feed: &Feed) -> String {
+  match kind {
+    FeedKind::Rss => render_rss(feed),
+    FeedKind::Atom => render_atom(feed),
+    FeedKind::Json => render_json(feed),
+  }
+}
+
+fn xml_text(text: &str) -> String {
+  let mut escaped = String::new();
+  escape_html(&mut escaped, text).unwrap();
+  escaped
+}
+
+fn feed_date(feed: &Feed) -> DateTime<FixedOffset> {
+  feed
+    .entries
+    .iter()
+    .filter_map(FeedEntry::date)
+    .max()
+    .unwrap_or_else(dates::now)
+}
+
+fn render_rss(feed: &Feed) -> String {
+  let mut xml = String::from(
+    "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\n<channel>\n",
+  );
+  xml.push_str(&format!(
+    "<title>{}</title>\n<link>{}</link>\n<description>{}</description>\n<atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>\n<lastBuildDate>{}</lastBuildDate>\n",
+    xml_text(&feed.title),
+    xml_text(&feed.home_url),
+    xml_text(&feed.title),
+    xml_text(&feed.feed_url),
+    feed_date(feed).to_rfc2822()
+  ));
+
+  for entry in &feed.entries {
+    xml.push_str("<item>\n");
+    xml.push_str(&format!(
+      "<title>{}</title>\n<link>{}</link>\n<guid isPermaLink=\"true\">{}</guid>\n",
+      xml_text(&entry.title),
+      xml_text(&entry.url),
+      xml_text(&entry.url)
+    ));
+    if let Some(date) = entry.published.or(entry.updated) {
+      xml.push_str(&format!("<pubDate>{}</pubDate>\n", date.to_rfc2822()));
+    }
+    if let Some(description) = entry.content.as_ref().or(entry.summary.as_ref()) {
+      xml.push_str(&format!(
+        "<description>{}</description>\n",
+        xml_text(description)
+      ));
+    }
+    for tag in &entry.tags {
+      xml.push_str(&format!("<category>{}</category>\n", xml_text(tag)));
+    }
+    xml.push_str("</item>\n");
+  }
+
+  xml.push_str("</channel>\n</rss>\n");
+  xml
+}
+
+fn render_atom(feed: &Feed) -> String {
+  let mut xml = String::from(
+    "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n",
+  );
+  xml.push_str(&format!(
+    "<title>{}</title>\n<id>{}</id>\n<link href=\"{}\"/>\n<link href=\"{}\" rel=\"self\"/>\n<updated>{}</updated>\n",
+    xml_text(&feed.title),
+    xml_text(&feed.feed_url),
+    xml_text(&feed.home_url),
+    xml_text(&feed.feed_url),
+    feed_date(feed).to_rfc3339()
+  ));
+
+  for entry in &feed.entries {
+    xml.push_str("<entry>\n");
+    xml.push_str(&format!(
+      "<title>{}</title>\n<id>{}</id>\n<link href=\"{}\"/>\n<updated>{}</updated>\n",
+      xml_text(&entry.title),
+      xml_text(&entry.url),
+      xml_text(&entry.url),
+      entry.date().unwrap_or_else(|| feed_date(feed)).to_rfc3339()
+    ));
+    if let Some(published) = entry.published {
+      xml.push_str(&format!(
+        "<published>{}</published>\n",
+        published.to_rfc3339()
+      ));
+    }
+    if let Some(summary) = &entry.summary {
+      xml.push_str(&format!("<summary>{}</summary>\n", xml_text(summary)));
+    }
+    if let Some(content) = &entry.content {
+      xml.push_str(&format!(
+        "<content type=\"html\">{}</content>\n",
+        xml_text(content)
+      ));
+    }
+    for tag in &entry.tags {
+      xml.push_str(&format!("<category term=\"{}\"/>\n", xml_text(tag)));
+    }
+    xml.push_str("</entry>\n");
+  }
+
+  xml.push_str("</feed>\n");
+  xml
+}
+
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+  version: &'static str,
+  title: &'a str,
+  home_page_url: &'a str,
+  feed_url: &'a str,
+  items: Vec<JsonFeedItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedItem<'a> {
+  id: &'a str,
+  url: &'a str,
+  title: &'a str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  summary: Option<&'a str>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  content_html: Option<&'a str>,
+  /// In the summary only mode, a content is required by the spec
+  #[serde(skip_serializing_if = "Option::is_none")]
+  content_text: Option<&'a str>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  date_published: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  date_modified: Option<String>,
+  #[serde(skip_serializing_if = "<[_]>::is_empty")]
+  tags: &'a [String],
+}
+
+fn render_json(feed: &Feed) -> String {
+  let json_feed = JsonFeed {
+    version: "https://jsonfeed.org/version/1.1",
+    title: &feed.title,
+    home_page_url: &feed.home_url,
+    feed_url: &feed.feed_url,
+    items: feed
+      .entries
+      .iter()
+      .map(|entry| JsonFeedItem {
+        id: &entry.url,
+        url: &entry.url,
+        title: &entry.title,
+        summary: entry.summary.as_deref(),
+        content_html: entry.content.as_deref(),
+        content_text: entry
+          .content
+          .is_none()
+          .then(|| entry.summary.as_deref().unwrap_or(&entry.title)),
+        date_published: entry.published.map(|date| date.to_rfc3339()),
+        date_modified: entry.updated.map(|date| date.to_rfc3339()),
+        tags: &entry.tags,
+      })
+      .collect(),
+  };
+  serde_json::to_string_pretty(&json_feed).unwrap()
+}
@@ -0,0 +1,243 @@
+use crate::{
+  app::Note,
+  config::{SlugStyle, UserConfig},
+  slug,
+};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::{BTreeMap, HashMap, HashSet},
+  fs,
+  path::{Path, PathBuf},
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Frontmatter fields giving the id of a note, in order
+const ID_FIELDS: [&str; 2] = ["id", "uid"];
+
+/// Where a note with a given id is, and where it was before
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct IdEntry {
+  /// Relative to the vault root and without extension, ex: `sub/note`
+  path: String,
+  /// Hash of the html content, to recognise a renamed note
+  content_hash: String,
+  /// Former paths of the note, the oldest first
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  previous_paths: Vec<String>,
+}
+
+/// Ids of the notes of a vault and their rename history, kept in the cache folder
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct IdCache {
+  #[serde(skip)]
+  file: Option<PathBuf>,
+  /// Whether the ids changed since the cache was read or saved
+  #[serde(skip)]
+  changed: bool,
+  notes: BTreeMap<String, IdEntry>,
+}
+
+impl IdCache {
+  /// Read the cache of the vault, an unreadable cache starts empty
+  pub fn load(config: &UserConfig) -> Self {
+    let file = cache_file(config, "ids");
+    let mut cache: IdCache = file
+      .as_ref()
+      .and_then(|file| fs::read_to_string(file).ok())
+      .and_then(|json| serde_json::from_str(&json).ok())
+      .unwrap_or_default();
+    cache.file = file;
+    cache
+  }
+
+  /// Write the cache when the ids changed, only the long-running index saves it
+  /// so that the commands reading a vault leave the cache folder untouched
+  pub fn save(&mut self) {
+    let Some(file) = &self.file else {
+      return;
+    };
+    if !self.changed {
+      return;
+    }
+    let result = file
+      .parent()
+      .map_or(Ok(()), fs::create_dir_all)
+      .and_then(|_| fs::write(file, serde_json::to_string_pretty(self).unwrap()));
+    match result {
+      Ok(()) => self.changed = false,
+      Err(err) => log::warn!(
+        "{} {}",
+        format!("× Failed to write the id cache {}:", file.display()).red(),
+        err
+      ),
+    }
+  }
+
+  /// Give every note its id: the frontmatter `id` or `uid`, else the id of the note
+  /// at the same path, else the id of a vanished note with the same content (a rename),
+  /// else a new one. Renames are recorded, the cache is written by `save`.
+  pub fn assign(&mut self, notes: &mut HashMap<String, Note>) {
+    let mut keys: Vec<String> = notes.keys().cloned().collect();
+    keys.sort();
+
+    let mut taken: HashSet<String> = HashSet::new();
+    let mut assigned: HashMap<String, String> = HashMap::new();
+
+    // Frontmatter ids first, they win over the generated ones
+    for key in &keys {
+      let Some(id) = frontmatter_id(&notes[key]) else {
+        continue;
+      };
+      if taken.insert(id.clone()) {
+        assigned.insert(key.clone(), id);
+      } else {
+        log::warn!(
+          "{} {}",
+          "× Duplicate note id, generating another one:".red(),
+          format!("{} ({})", notes[key].path, id).bold()
+        );
+      }
+    }
+
+    let by_path: HashMap<&str, &str> = self
+      .notes
+      .iter()
+      .map(|(id, entry)| (entry.path.as_str(), id.as_str()))
+      .collect();
+    for key in &keys {
+      if assigned.contains_key(key) {
+        continue;
+      }
+      let path = note_path(&notes[key]);
+      if let Some(id) = by_path.get(path.as_str()) {
+        if taken.insert(id.to_string()) {
+          assigned.insert(key.clone(), id.to_string());
+        }
+      }
+    }
+
+    // The notes left are new or renamed
+    let current_paths: HashSet<String> = notes.values().map(note_path).collect();
+    for key in &keys {
+      if assigned.contains_key(key) {
+        continue;
+      }
+      let hash = content_hash(&notes[key].content);
+      let renamed = self
+        .notes
+        .iter()
+        .find(|(id, entry)| {
+          !taken.contains(*id) && entry.content_hash == hash && !current_paths.contains(&entry.path)
+        })
+        .map(|(id, _)| id.clone());
+      let id = renamed.unwrap_or_else(|| self.generate_id(&notes[key], &taken));
+      taken.insert(id.clone());
+      assigned.insert(key.clone(), id);
+    }
+
+    let mut changed = self.changed;
+    for (key, id) in assigned {
+      let note = notes.get_mut(&key).unwrap();
+      let path = note_path(note);
+      let hash = content_hash(&note.content);
+      note.id = id.clone();
+
+      let entry = self.notes.entry(id).or_insert_with(|| {
+        changed = true;
+        IdEntry {
+          path: path.clone(),
+          content_hash: hash.clone(),
+          previous_paths: Vec::new(),
+        }
+      });
+      if entry.path != path {
+        let previous = std::mem::replace(&mut entry.path, path.clone());
+        entry
+          .previous_paths
+          .retain(|old| *old != path && *old != previous);
+        entry.previous_paths.push(previous);
+        changed = true;
+      }
+      if entry.content_hash != hash {
+        entry.content_hash = hash;
+        changed = true;
+      }
+    }
+
+    self.changed = changed;
+  }
+
+  /// Current path of a note that was at `path` before being renamed,
+  /// `path` being the former path or its slug
+  pub fn renamed_path(&self, path: &str, style: SlugStyle) -> Option<&str> {
+    let wanted = slug::lookup_key(path);
+    let was_at = |old: &String| {
+      slug::lookup_key(old) == wanted || slug::lookup_key(&slug::slug_path(old, style)) == wanted
+    };
+    self
+      .notes
+      .values()
+      .filter(|entry| !was_at(&entry.path))
+      .find(|entry| entry.previous_paths.iter().any(was_at))
+      .map(|entry| entry.path.as_str())
+  }
+
+  fn generate_id(&self, note: &Note, taken: &HashSet<String>) -> String {
+    let nanos = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|duration| duration.as_nanos())
+      .unwrap_or_default();
+    let mut seed = format!("{}:{}", note.path, nanos);
+    loop {
+      let id = format!("{:016x}", fnv1a(seed.as_bytes()))[..10].to_string();
+      if !taken.contains(&id) && !self.notes.contains_key(&id) {
+        return id;
+      }
+      seed.push('+');
+    }
+  }
+}
+
+/// `{cache_dir}/{name}-{root folder}-{hash of the root}.json`, one file per vault
+pub fn cache_file(config: &UserConfig, name: &str) -> Option<PathBuf> {
+  let cache_dir = config.cache_dir.as_ref()?;
+  let root_name = Path::new(&config.root_path)
+    .file_name()
+    .map(|name| name.to_string_lossy().to_string())
+    .unwrap_or_default();
+  Some(Path::new(cache_dir).join(format!(
+    "{}-{}-{:08x}.json",
+    name,
+    root_name,
+    fnv1a(config.root_path.as_bytes()) as u32
+  )))
+}
+
+fn frontmatter_id(note: &Note) -> Option<String> {
+  let extra = note.data.metadata.extra.as_ref()?;
+  ID_FIELDS.iter().find_map(|field| match extra.get(*field)? {
+    serde_yaml_ng::Value::String(id) if !id.trim().is_empty() => Some(id.trim().to_string()),
+    serde_yaml_ng::Value::Number(id) => Some(id.to_string()),
+    _ => None,
+  })
+}
+
+/// Path of the note as in `/files/{path}`
+fn note_path(note: &Note) -> String {
+  note
+    .relative_file_path()
+    .trim_end_matches(".md")
+    .to_string()
+}
+
+fn content_hash(content: &str) -> String {
+  format!("{:016x}", fnv1a(content.as_bytes()))
+}
+
+/// Stable across builds, unlike the hasher of the standard library
+fn fnv1a(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+    (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+  })
+}
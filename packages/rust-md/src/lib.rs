@@ -0,0 +1,235 @@
+//! Index, watch and serve vaults of markdown notes.
+//!
+//! The `rust-md` server and the desktop app are both built on this library.
+//!
+//! Its api is the items exported here: a [`Vault`] to open, query and watch,
+//! the [`Note`], [`Metadata`] and [`Link`] models, the config of a vault
+//! ([`UserConfig`]) and the markdown renderer [`render_markdown`]. The fallible
+//! functions return their errors as text, [`ConfigErrors`] converts into it.
+//!
+//! ```no_run
+//! use rust_md::{NoteFilter, NoteSort, Vault};
+//!
+//! # async fn run() -> Result<(), String> {
+//! // The vault named `journal`, indexed from the notes of this folder
+//! let config = rust_md::folder_config("/home/me/journal")?;
+//! let vault = Vault::open("journal", config).await?;
+//!
+//! let filter = NoteFilter {
+//!   tags: vec!["rust".to_string()],
+//!   ..Default::default()
+//! };
+//! for note in vault.query(&filter, NoteSort::Updated).await {
+//!   println!("{}", note.path);
+//! }
+//!
+//! let mut events = vault.subscribe();
+//! tokio::spawn(vault.watch());
+//! while let Ok(event) = events.recv().await {
+//!   println!("{:?}", event);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Progress and warnings are written with the [`log`](https://docs.rs/log) crate.
+
+use crate::config::DEFAULT_VAULT;
+use crate::service::find_all_notes;
+use colored::Colorize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex, RwLock};
+
+pub(crate) mod app;
+pub(crate) mod auth;
+pub(crate) mod cli;
+pub(crate) mod config;
+pub(crate) mod dates;
+pub(crate) mod feed;
+pub(crate) mod ids;
+pub(crate) mod matcher;
+pub(crate) mod pages;
+pub(crate) mod parser;
+pub(crate) mod policy;
+pub(crate) mod redact;
+pub(crate) mod reload;
+pub(crate) mod sanitizer;
+pub(crate) mod schema;
+pub(crate) mod search;
+pub(crate) mod seo;
+pub(crate) mod server;
+pub(crate) mod service;
+pub(crate) mod share;
+pub(crate) mod site;
+pub(crate) mod slug;
+pub(crate) mod stats;
+pub(crate) mod templates;
+pub(crate) mod validation;
+pub(crate) mod vault_path;
+pub(crate) mod watcher;
+
+pub use app::{Note, NoteData};
+pub use cli::run as run_cli;
+pub use config::{
+  folder_config, load_config, AuthConfig, DateFallback, DatesConfig, FeedContent, FieldSchema,
+  FieldType, PrivateConfig, RawHtml, SanitizeConfig, Scope, ServerConfig, SiteConfig, SlugStyle,
+  TlsConfig, TokenConfig, TypeSchema, UserConfig, VaultConfig, VisibilityRule,
+};
+pub use dates::{DateSource, NoteDates};
+pub use pages::{Graph, NodeInfo, PageLink, TreeFolder};
+pub use parser::{Heading, Link, LinkStyle, Metadata};
+pub use schema::{SchemaViolation, TypedFields};
+pub use search::{NoteFilter, NoteSort};
+pub use seo::Seo;
+pub use stats::VaultStats;
+pub use validation::{ConfigError, ConfigErrors};
+pub use watcher::IndexEvent;
+
+/// Render a note to html, along with its front matter and its links to other notes.
+///
+/// `file_path` is the path of the note on disk, `file_name` its name without extension.
+pub fn render_markdown(
+  file_path: &str,
+  file_name: &str,
+  source_markdown: &str,
+  config: &UserConfig,
+  link_style: LinkStyle,
+) -> Result<(String, Metadata, Vec<Link>), String> {
+  parser::render_markdown(file_path, file_name, source_markdown, config, link_style)
+    .map_err(|err| format!("Failed to convert '{}': {:#}", file_path, err))
+}
+
+/// Events kept for the subscribers lagging behind
+const EVENTS_CAPACITY: usize = 256;
+
+/// An indexed vault of notes
+///
+/// Cloning a vault is cheap, the clones share the same index.
+#[derive(Clone)]
+pub struct Vault {
+  pub(crate) name: String,
+  pub(crate) notes: Arc<Mutex<HashMap<String, Note>>>,
+  /// Reloaded when the config file changes
+  pub(crate) config: Arc<RwLock<UserConfig>>,
+  /// Reloaded when a template or the config file changes
+  pub(crate) templates: Arc<RwLock<templates::Templates>>,
+  pub(crate) index: Arc<RwLock<stats::IndexInfo>>,
+  /// Stable ids of the notes, saved in the cache folder
+  pub(crate) ids: Arc<Mutex<ids::IdCache>>,
+  /// Signs the share links and keeps the revoked ones
+  pub(crate) shares: Arc<Mutex<share::Shares>>,
+  /// Changes of the index, see `subscribe`
+  pub(crate) events: broadcast::Sender<IndexEvent>,
+}
+
+impl Vault {
+  /// Open and index a vault, `watch` keeps the index up to date afterwards
+  pub async fn open(name: &str, config: UserConfig) -> Result<Self, String> {
+    let vault = Vault {
+      name: name.to_string(),
+      notes: Arc::new(Mutex::new(HashMap::new())),
+      templates: Arc::new(RwLock::new(templates::Templates::load(&config))),
+      index: Arc::new(RwLock::new(stats::IndexInfo::default())),
+      ids: Arc::new(Mutex::new(ids::IdCache::load(&config))),
+      shares: Arc::new(Mutex::new(share::Shares::load(name, &config))),
+      events: broadcast::channel(EVENTS_CAPACITY).0,
+      config: Arc::new(RwLock::new(config)),
+    };
+
+    log::info!(
+      "{} {}",
+      "Indexing all files of vault".yellow().bold(),
+      name.bold()
+    );
+    let start = std::time::Instant::now();
+    vault.index().await?;
+    log::info!("Indexed in: {}", format!("{:?}", start.elapsed()).bold());
+    Ok(vault)
+  }
+
+  /// Index the whole vault again, the subscribers receive `IndexEvent::Reindexed`
+  pub async fn index(&self) -> Result<(), String> {
+    let start = std::time::Instant::now();
+    let config = self.config.read().await.clone();
+
+    let notes = Arc::new(Mutex::new(HashMap::new()));
+    find_all_notes(&PathBuf::from(&config.root_path), notes.clone(), &config)
+      .await
+      .map_err(|err| format!("Error while searching for files: {}", err))?;
+    let mut notes = std::mem::take(&mut *notes.lock().await);
+
+    {
+      let mut ids = self.ids.lock().await;
+      ids.assign(&mut notes);
+      ids.save();
+    }
+    slug::report_collisions(&notes);
+    *self.notes.lock().await = notes;
+    self.index.write().await.indexed(start);
+    let _ = self.events.send(IndexEvent::Reindexed);
+    Ok(())
+  }
+
+  /// Name of the vault, `default` for the vault served at the root
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// Whether the vault is served at the root rather than under `/vaults/{name}`
+  pub fn is_default(&self) -> bool {
+    self.name == DEFAULT_VAULT
+  }
+
+  /// The config the vault was opened or last reloaded with
+  pub async fn config(&self) -> UserConfig {
+    self.config.read().await.clone()
+  }
+
+  /// A note by its path in the vault or its slug, ex: `sub/note`, whatever the case
+  pub async fn get(&self, path: &str) -> Option<Note> {
+    let notes = self.notes.lock().await;
+    slug::find_note(&notes, path).map(|key| notes[key].clone())
+  }
+
+  /// Notes matching the filter, see `NoteSort` for their order
+  pub async fn query(&self, filter: &NoteFilter, sort: NoteSort) -> Vec<Note> {
+    let notes = self.notes.lock().await;
+    search::search(notes.values(), filter, sort)
+      .into_iter()
+      .cloned()
+      .collect()
+  }
+
+  /// Folders and notes of the vault, with urls relative to `base_url`
+  pub async fn tree(&self, base_url: &str) -> TreeFolder {
+    let config = self.config.read().await.clone();
+    pages::folder_tree(&*self.notes.lock().await, &config, base_url)
+  }
+
+  /// Visible notes and the links between them
+  pub async fn graph(&self) -> Graph {
+    let config = self.config.read().await.clone();
+    pages::note_graph(&*self.notes.lock().await, &config)
+  }
+
+  /// Counts of the notes, tags, links and assets of the vault
+  pub async fn stats(&self) -> VaultStats {
+    let config = self.config.read().await.clone();
+    stats::vault_stats(&*self.notes.lock().await, &config)
+  }
+
+  /// Watch the files of the vault and update its index, until the watcher fails
+  pub async fn watch(self) -> Result<(), String> {
+    let root_path = self.config.read().await.root_path.clone();
+    watcher::watch_files(&root_path, self)
+      .await
+      .map_err(|err| format!("Failed to watch '{}': {:#}", root_path, err))
+  }
+
+  /// Receive the changes of the index
+  pub fn subscribe(&self) -> broadcast::Receiver<IndexEvent> {
+    self.events.subscribe()
+  }
+}
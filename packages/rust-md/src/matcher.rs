@@ -0,0 +1,187 @@
+use crate::config::UserConfig;
+use colored::Colorize;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+  sync::Mutex,
+};
+
+/// Ignore file read in the folders of the vault, same syntax as `.gitignore`
+pub const IGNORE_FILE: &str = ".rustmdignore";
+
+/// Gitignore style matcher built from the `ignore` config and the `.rustmdignore` files.
+/// Like git, the ignore file of a folder applies to its content and wins over the
+/// files of the folders above.
+pub struct IgnoreMatcher {
+  root: PathBuf,
+  gitignore: Gitignore,
+  /// Ignore files of the sub folders, read when a path below them is matched
+  nested: Mutex<HashMap<PathBuf, Option<Gitignore>>>,
+}
+
+impl IgnoreMatcher {
+  pub fn new(config: &UserConfig) -> Self {
+    let root = PathBuf::from(&config.root_path);
+    let mut builder = GitignoreBuilder::new(&root);
+
+    for pattern in &config.ignore {
+      if let Err(err) = builder.add_line(None, pattern) {
+        log::warn!(
+          "{} {}",
+          "× Invalid ignore pattern:".red(),
+          format!("'{}' {}", pattern, err).bold()
+        );
+      }
+    }
+
+    add_ignore_file(&mut builder, &root);
+    let gitignore = build(builder);
+
+    Self {
+      root,
+      gitignore,
+      nested: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// Whether the path, or one of its parent folders, is ignored.
+  /// Paths outside of the vault are never ignored.
+  pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+    let Ok(relative_path) = path.strip_prefix(&self.root) else {
+      return false;
+    };
+
+    let components: Vec<_> = relative_path.components().collect();
+    let mut current = self.root.clone();
+    for (i, component) in components.iter().enumerate() {
+      current.push(component);
+      let current_is_dir = is_dir || i + 1 < components.len();
+      if self.matches(&current, current_is_dir) {
+        return true;
+      }
+    }
+    false
+  }
+
+  /// Whether the path itself is ignored, the deepest ignore file matching it decides
+  fn matches(&self, path: &Path, is_dir: bool) -> bool {
+    let mut nested = self.nested.lock().unwrap();
+    for folder in path.ancestors().skip(1) {
+      let matched = if folder == self.root {
+        self.gitignore.matched(path, is_dir)
+      } else {
+        let gitignore = nested
+          .entry(folder.to_path_buf())
+          .or_insert_with(|| nested_ignore_file(folder));
+        match gitignore {
+          Some(gitignore) => gitignore.matched(path, is_dir),
+          None => continue,
+        }
+      };
+      if !matched.is_none() || folder == self.root {
+        return matched.is_ignore();
+      }
+    }
+    false
+  }
+}
+
+/// The ignore file of a sub folder, when there is one
+fn nested_ignore_file(folder: &Path) -> Option<Gitignore> {
+  if !folder.join(IGNORE_FILE).is_file() {
+    return None;
+  }
+  let mut builder = GitignoreBuilder::new(folder);
+  add_ignore_file(&mut builder, folder);
+  Some(build(builder))
+}
+
+fn add_ignore_file(builder: &mut GitignoreBuilder, folder: &Path) {
+  let ignore_file = folder.join(IGNORE_FILE);
+  if !ignore_file.is_file() {
+    return;
+  }
+  if let Some(err) = builder.add(&ignore_file) {
+    log::warn!(
+      "{} {}",
+      format!("× Invalid {}:", ignore_file.display()).red(),
+      format!("{}", err).bold()
+    );
+  }
+}
+
+fn build(builder: GitignoreBuilder) -> Gitignore {
+  builder.build().unwrap_or_else(|err| {
+    log::warn!("{} {}", "× Failed to build ignore patterns:".red(), err);
+    Gitignore::empty()
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{IgnoreMatcher, IGNORE_FILE};
+  use crate::config::folder_config;
+  use std::{fs, path::Path};
+
+  fn matcher(root: &Path, ignore: &[&str]) -> IgnoreMatcher {
+    let mut config = folder_config(&root.to_string_lossy()).unwrap();
+    config.ignore = ignore.iter().map(|pattern| pattern.to_string()).collect();
+    IgnoreMatcher::new(&config)
+  }
+
+  #[test]
+  fn negated_patterns_are_kept() {
+    let root = tempfile::tempdir().unwrap();
+    let matcher = matcher(root.path(), &["*.md", "!keep.md", "drafts/"]);
+    let is_ignored = |path: &str| matcher.is_ignored(&root.path().join(path), false);
+
+    assert!(is_ignored("note.md"));
+    assert!(!is_ignored("keep.md"));
+    assert!(!is_ignored("image.png"));
+    // A file can't be kept when its folder is ignored
+    assert!(is_ignored("drafts/keep.md"));
+  }
+
+  #[test]
+  fn directory_patterns_match_folders_only() {
+    let root = tempfile::tempdir().unwrap();
+    let matcher = matcher(root.path(), &["build/", "/top"]);
+    let root = root.path();
+
+    assert!(matcher.is_ignored(&root.join("build"), true));
+    assert!(!matcher.is_ignored(&root.join("build"), false));
+    assert!(matcher.is_ignored(&root.join("sub/build/note.md"), false));
+    assert!(!matcher.is_ignored(&root.join("not_build/note.md"), false));
+    // Anchored to the root of the vault
+    assert!(matcher.is_ignored(&root.join("top/note.md"), false));
+    assert!(!matcher.is_ignored(&root.join("sub/top/note.md"), false));
+  }
+
+  #[test]
+  fn nested_ignore_files_apply_to_their_folder() {
+    let root = tempfile::tempdir().unwrap();
+    fs::write(root.path().join(IGNORE_FILE), "*.tmp.md\nlocal.md\n").unwrap();
+    fs::create_dir_all(root.path().join("sub/deep")).unwrap();
+    fs::write(
+      root.path().join("sub").join(IGNORE_FILE),
+      "/anchored.md\n!local.md\nsecret/\n",
+    )
+    .unwrap();
+    let matcher = matcher(root.path(), &[]);
+    let is_ignored = |path: &str| matcher.is_ignored(&root.path().join(path), false);
+
+    assert!(is_ignored("a.tmp.md"));
+    assert!(is_ignored("sub/deep/b.tmp.md"));
+    // Relative to the folder of the ignore file
+    assert!(is_ignored("sub/anchored.md"));
+    assert!(!is_ignored("anchored.md"));
+    assert!(!is_ignored("sub/deep/anchored.md"));
+    // The deeper file wins over the root one
+    assert!(is_ignored("local.md"));
+    assert!(!is_ignored("sub/local.md"));
+    assert!(!is_ignored("sub/deep/local.md"));
+    assert!(is_ignored("sub/secret/note.md"));
+    assert!(!is_ignored("secret/note.md"));
+  }
+}
@@ -0,0 +1,447 @@
+use crate::{
+  app::Note,
+  config::UserConfig,
+  parser::{self, Heading, Link},
+  seo::SeoMeta,
+  slug,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::{BTreeMap, BTreeSet, HashMap},
+  fs,
+  path::Path,
+};
+
+/// Notes and their links, for a graph view
+#[derive(Serialize, Deserialize)]
+pub struct Graph {
+  pub nodes: Vec<NodeInfo>,
+  pub links: Vec<Link>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NodeInfo {
+  pub id: String,
+  pub public: bool,
+  pub path: String,
+  pub r#type: String,
+}
+
+/// Link to another page of the vault
+#[derive(Serialize, Debug, Clone)]
+pub struct PageLink {
+  pub name: String,
+  pub url: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TagInfo {
+  pub name: String,
+  pub count: usize,
+  pub url: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Navigation {
+  /// Parent folders, from the root
+  pub breadcrumbs: Vec<PageLink>,
+  pub folder: PageLink,
+  /// Visible notes of the same folder, in the order of the folder page
+  pub previous: Option<PageLink>,
+  pub next: Option<PageLink>,
+}
+
+/// A folder of the vault with its visible notes and sub folders
+#[derive(Serialize, Debug)]
+pub struct TreeFolder {
+  pub name: String,
+  /// Relative to the vault root, empty for the root
+  pub path: String,
+  pub url: String,
+  /// Notes directly in the folder
+  pub count: usize,
+  /// Notes in the folder and its sub folders
+  pub total: usize,
+  /// `{folder}/{folder}.md` or `{folder}/index.md`, describing the folder
+  pub folder_note: Option<PageLink>,
+  pub folders: Vec<TreeFolder>,
+  pub notes: Vec<PageLink>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct NotePage<'a> {
+  pub base_url: String,
+  pub title: String,
+  pub note: &'a Note,
+  /// Visible notes linking to this one
+  pub backlinks: Vec<PageLink>,
+  pub outline: Vec<Heading>,
+  pub navigation: Navigation,
+  /// OpenGraph and Twitter card fields, for the layout
+  pub meta: Option<SeoMeta>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TagsPage {
+  pub base_url: String,
+  /// The tag of the page, `None` for the list of tags
+  pub tag: Option<String>,
+  pub tags: Vec<TagInfo>,
+  pub notes: Vec<PageLink>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FolderPage {
+  pub base_url: String,
+  pub path: String,
+  pub name: String,
+  pub folders: Vec<PageLink>,
+  pub notes: Vec<PageLink>,
+}
+
+/// Visible notes and their links, links to hidden notes are left out
+pub fn note_graph(notes: &HashMap<String, Note>, config: &UserConfig) -> Graph {
+  let mut nodes = Vec::new();
+  let mut links = Vec::new();
+
+  for (file_name, converted_note) in notes.iter().filter(|(_, note)| is_visible(note, config)) {
+    nodes.push(NodeInfo {
+      id: file_name.clone(),
+      public: converted_note.public,
+      path: format!("/{}", file_name),
+      r#type: "note".to_string(),
+    });
+
+    // ! Extract links from the note
+    links.extend(
+      converted_note
+        .data
+        .visible_links(config.private.include)
+        .into_iter()
+        .filter(|link| link.target_public.unwrap_or(false) || config.private.include),
+    );
+  }
+
+  Graph { nodes, links }
+}
+
+pub fn is_visible(note: &Note, config: &UserConfig) -> bool {
+  note.public || config.private.include
+}
+
+pub fn note_title(note: &Note) -> String {
+  note
+    .data
+    .metadata
+    .title
+    .clone()
+    .unwrap_or_else(|| note.name.clone())
+}
+
+/// Url of a file, `path` being relative to the vault root
+pub fn page_url(base_url: &str, path: &str) -> String {
+  format!("{}/files/{}", base_url, slug::encode_path(path))
+    .trim_end_matches('/')
+    .to_string()
+}
+
+/// Url of a folder, with the slug style of the vault
+pub fn folder_url(base_url: &str, path: &str, config: &UserConfig) -> String {
+  page_url(base_url, &slug::slug_path(path, config.slug_style))
+}
+
+pub fn note_url(base_url: &str, note: &Note) -> String {
+  page_url(base_url, &note.slug)
+}
+
+fn note_link(base_url: &str, note: &Note) -> PageLink {
+  PageLink {
+    name: note_title(note),
+    url: note_url(base_url, note),
+  }
+}
+
+fn sorted_links(mut links: Vec<PageLink>) -> Vec<PageLink> {
+  links.sort_by_key(|link| link.name.to_lowercase());
+  links
+}
+
+pub fn note_page<'a>(
+  notes: &HashMap<String, Note>,
+  note: &'a Note,
+  config: &UserConfig,
+  base_url: &str,
+) -> NotePage<'a> {
+  let relative_path = note.relative_file_path();
+
+  let backlinks = notes
+    .values()
+    .filter(|source| is_visible(source, config))
+    .filter(|source| {
+      source
+        .data
+        .visible_links(config.private.include)
+        .iter()
+        .any(|link| {
+          link.target_vault.is_none()
+            && parser::vault_relative_path(&link.target_path, &config.root_path) == relative_path
+        })
+    })
+    .map(|source| note_link(base_url, source))
+    .collect();
+
+  let outline = fs::read_to_string(Path::new(&config.root_path).join(&relative_path))
+    .map(|source| parser::markdown_outline(&source, config.private.include))
+    .unwrap_or_default();
+
+  NotePage {
+    base_url: base_url.to_string(),
+    title: note_title(note),
+    note,
+    backlinks: sorted_links(backlinks),
+    outline,
+    navigation: navigation(notes, note, config, base_url),
+    meta: None,
+  }
+}
+
+/// Parent folders of a note and its siblings
+pub fn navigation(
+  notes: &HashMap<String, Note>,
+  note: &Note,
+  config: &UserConfig,
+  base_url: &str,
+) -> Navigation {
+  let relative_path = note.relative_file_path();
+
+  let mut breadcrumbs = vec![PageLink {
+    name: "Index".to_string(),
+    url: folder_url(base_url, "", config),
+  }];
+  let mut folder_path = String::new();
+  let folders: Vec<&str> = relative_path.split('/').collect();
+  for name in &folders[..folders.len() - 1] {
+    if !folder_path.is_empty() {
+      folder_path.push('/');
+    }
+    folder_path.push_str(name);
+    breadcrumbs.push(PageLink {
+      name: name.to_string(),
+      url: folder_url(base_url, &folder_path, config),
+    });
+  }
+  let folder = breadcrumbs.last().cloned().unwrap();
+
+  let parent = parent_path(&relative_path);
+  let mut siblings: Vec<&Note> = notes
+    .values()
+    .filter(|sibling| is_visible(sibling, config))
+    .filter(|sibling| parent_path(&sibling.relative_file_path()) == parent)
+    .collect();
+  siblings.sort_by_key(|sibling| (note_title(sibling).to_lowercase(), sibling.path.clone()));
+  let position = siblings
+    .iter()
+    .position(|sibling| sibling.path == note.path);
+
+  Navigation {
+    breadcrumbs,
+    folder,
+    previous: position
+      .and_then(|position| position.checked_sub(1))
+      .map(|position| note_link(base_url, siblings[position])),
+    next: position
+      .and_then(|position| siblings.get(position + 1))
+      .map(|sibling| note_link(base_url, sibling)),
+  }
+}
+
+/// `sub/note.md` -> `sub`
+pub(crate) fn parent_path(relative_path: &str) -> &str {
+  relative_path
+    .rsplit_once('/')
+    .map(|(parent, _)| parent)
+    .unwrap_or_default()
+}
+
+/// Folders of the vault holding visible notes, from the root
+pub fn folder_tree(
+  notes: &HashMap<String, Note>,
+  config: &UserConfig,
+  base_url: &str,
+) -> TreeFolder {
+  let mut visible: Vec<&Note> = notes
+    .values()
+    .filter(|note| is_visible(note, config))
+    .collect();
+  visible.sort_by_key(|note| note.path.clone());
+
+  let mut root = tree_folder(String::new(), base_url, config);
+  for note in visible {
+    let relative_path = note.relative_file_path();
+    let mut folder = &mut root;
+    folder.total += 1;
+    let parts: Vec<&str> = relative_path.split('/').collect();
+    for name in &parts[..parts.len() - 1] {
+      let index = match folder.folders.iter().position(|sub| sub.name == *name) {
+        Some(index) => index,
+        None => {
+          let path = if folder.path.is_empty() {
+            name.to_string()
+          } else {
+            format!("{}/{}", folder.path, name)
+          };
+          folder.folders.push(tree_folder(path, base_url, config));
+          folder.folders.len() - 1
+        }
+      };
+      folder = &mut folder.folders[index];
+      folder.total += 1;
+    }
+
+    let file_stem = parts[parts.len() - 1].trim_end_matches(".md");
+    if file_stem == "index" || (!folder.path.is_empty() && file_stem == folder.name) {
+      // `{folder}.md` wins over `index.md`
+      if folder.folder_note.is_none() || file_stem != "index" {
+        folder.folder_note = Some(note_link(base_url, note));
+      }
+    }
+    folder.count += 1;
+    folder.notes.push(note_link(base_url, note));
+  }
+
+  sort_tree(&mut root);
+  root
+}
+
+fn tree_folder(path: String, base_url: &str, config: &UserConfig) -> TreeFolder {
+  TreeFolder {
+    name: path
+      .rsplit('/')
+      .next()
+      .filter(|name| !name.is_empty())
+      .unwrap_or("Index")
+      .to_string(),
+    url: folder_url(base_url, &path, config),
+    path,
+    count: 0,
+    total: 0,
+    folder_note: None,
+    folders: Vec::new(),
+    notes: Vec::new(),
+  }
+}
+
+fn sort_tree(folder: &mut TreeFolder) {
+  folder.folders.sort_by_key(|sub| sub.name.to_lowercase());
+  folder.notes = sorted_links(std::mem::take(&mut folder.notes));
+  for sub in &mut folder.folders {
+    sort_tree(sub);
+  }
+}
+
+/// List of the tags, or of the notes having `tag`
+pub fn tags_page(
+  notes: &HashMap<String, Note>,
+  tag: Option<&str>,
+  config: &UserConfig,
+  base_url: &str,
+) -> TagsPage {
+  let visible: Vec<&Note> = notes
+    .values()
+    .filter(|note| is_visible(note, config))
+    .collect();
+
+  let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+  for note in &visible {
+    for name in note.data.metadata.tags.iter().flatten() {
+      *counts.entry(name).or_default() += 1;
+    }
+  }
+
+  let tags = counts
+    .into_iter()
+    .map(|(name, count)| TagInfo {
+      name: name.to_string(),
+      count,
+      url: format!("{}/tags/{}", base_url, slug::encode_path(name)),
+    })
+    .collect();
+
+  let notes = match tag {
+    Some(tag) => visible
+      .iter()
+      .filter(|note| {
+        note
+          .data
+          .metadata
+          .tags
+          .as_ref()
+          .is_some_and(|tags| tags.iter().any(|name| name == tag))
+      })
+      .map(|note| note_link(base_url, note))
+      .collect(),
+    None => Vec::new(),
+  };
+
+  TagsPage {
+    base_url: base_url.to_string(),
+    tag: tag.map(String::from),
+    tags,
+    notes: sorted_links(notes),
+  }
+}
+
+/// Notes and sub folders of a folder, `None` when it holds no visible note
+pub fn folder_page(
+  notes: &HashMap<String, Note>,
+  path: &str,
+  config: &UserConfig,
+  base_url: &str,
+) -> Option<FolderPage> {
+  let path = path.trim_matches('/');
+  let prefix = if path.is_empty() {
+    String::new()
+  } else {
+    format!("{}/", path)
+  };
+
+  let mut folders = BTreeSet::new();
+  let mut folder_notes = Vec::new();
+  let mut found = false;
+  for note in notes.values().filter(|note| is_visible(note, config)) {
+    let relative_path = note.relative_file_path();
+    let Some(rest) = relative_path.strip_prefix(&prefix) else {
+      continue;
+    };
+    found = true;
+    match rest.split_once('/') {
+      Some((folder, _)) => {
+        folders.insert(folder.to_string());
+      }
+      None => folder_notes.push(note_link(base_url, note)),
+    }
+  }
+
+  if !found {
+    return None;
+  }
+
+  Some(FolderPage {
+    base_url: base_url.to_string(),
+    path: path.to_string(),
+    name: path
+      .rsplit('/')
+      .next()
+      .filter(|name| !name.is_empty())
+      .unwrap_or("Index")
+      .to_string(),
+    folders: folders
+      .into_iter()
+      .map(|name| PageLink {
+        url: folder_url(base_url, &format!("{}{}", prefix, name), config),
+        name,
+      })
+      .collect(),
+    notes: sorted_links(folder_notes),
+  })
+}
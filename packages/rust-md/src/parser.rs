@@ -1,13 +1,19 @@
 use pulldown_cmark::{html::push_html, CowStr, Event, Options, Parser, Tag, TagEnd};
+use pulldown_cmark_escape::{escape_href, escape_html};
 use serde::{Deserialize, Serialize};
 use std::{
   collections::HashMap,
   fs,
+  ops::Range,
   path::{Component, Path, PathBuf},
 };
 
-use crate::config::UserConfig;
+use crate::{
+  config::{RawHtml, UserConfig},
+  policy, redact, sanitizer, slug,
+};
 
+/// Front matter of a note, every field is optional
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Metadata {
   pub r#type: Option<String>,
@@ -23,12 +29,39 @@ pub struct Metadata {
   pub extra: Option<HashMap<String, serde_yaml_ng::Value>>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A link from a note to another note
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Link {
+  /// Name of the note containing the link
   pub source: String,
+  /// Name of the linked note
   pub target: String,
+  /// Path of the linked file on disk
   pub target_path: String,
+  /// Whether the linked note is public, false when it does not exist
   pub target_public: Option<bool>,
+  /// Set when the link points to another vault
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub target_vault: Option<String>,
+}
+
+/// A heading of a note, for its outline
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Heading {
+  pub level: u8,
+  pub text: String,
+}
+
+/// Prefix of links to a note of another vault, `vault:name/path/to/note.md`
+pub const VAULT_LINK_PREFIX: &str = "vault:";
+
+/// How links to other notes are written in the html
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkStyle {
+  /// Paths served by the webserver, without extension
+  Server,
+  /// Relative paths to the exported `.html` files
+  Static,
 }
 
 /// Transform markdown to html and extract links
@@ -39,15 +72,38 @@ pub fn markdown_to_html(
   _private_links: bool,
   config: &UserConfig,
 ) -> anyhow::Result<(String, Metadata, Vec<Link>)> {
+  render_markdown(
+    file_path,
+    file_name,
+    source_markdown,
+    config,
+    LinkStyle::Server,
+  )
+}
+
+/// Same as `markdown_to_html`, with the links written in the given style
+pub fn render_markdown(
+  file_path: &str,
+  file_name: &str,
+  source_markdown: &str,
+  config: &UserConfig,
+  link_style: LinkStyle,
+) -> anyhow::Result<(String, Metadata, Vec<Link>)> {
+  // Static pages can't use absolute paths, the site may be hosted in a sub folder
+  let to_root = "../".repeat(
+    vault_relative_path(file_path, &config.root_path)
+      .matches('/')
+      .count(),
+  );
+
   // Extract metadata
   let metadata = markdown_to_metadata(source_markdown)?;
 
+  // Remove private sections, along with the links they contain
+  let source_markdown = &redact::private_sections(source_markdown, config.private.include);
+
   // Parser options
-  let mut options = Options::empty();
-  options.insert(Options::ENABLE_YAML_STYLE_METADATA_BLOCKS);
-  options.insert(Options::ENABLE_STRIKETHROUGH);
-  // Disabled since it only enable blockquote but doesn't support blockquote title and as limited types of callouts
-  // options.insert(Options::ENABLE_GFM);
+  let options = markdown_options();
 
   // File links
   let mut links: Vec<Link> = Vec::new();
@@ -55,8 +111,16 @@ pub fn markdown_to_html(
   // used to check if we're in a link tag (inside the parser loop)
   let mut in_link_tag = false; // used to check if we're in a link tag
 
+  // used to close external links written as raw html
+  let mut in_external_link = false;
+
+  // Raw html can be removed before rendering
+  let strip_raw_html = strips_raw_html(file_path, &metadata, config);
+
   // Parse the Markdown content
-  let parser = Parser::new_ext(source_markdown, options).map(|event| match event {
+  let events = Parser::new_ext(source_markdown, options)
+    .filter(|event| !(strip_raw_html && matches!(event, Event::Html(_) | Event::InlineHtml(_))));
+  let parser = events.map(|event| match event {
     Event::Start(tag) => match tag {
       Tag::Link {
         id,
@@ -64,34 +128,29 @@ pub fn markdown_to_html(
         dest_url,
         title,
       } => {
-        if !dest_url.starts_with("http") && dest_url.ends_with(".md") {
+        if let (true, Some(rel)) = (is_external_url(&dest_url), &config.sanitize.link_rel) {
+          in_external_link = true;
+          return Event::InlineHtml(CowStr::from(external_link_open_tag(&dest_url, &title, rel)));
+        }
+        if is_note_link(&dest_url) {
           // Used to rewrite link text
           in_link_tag = true;
 
-          let stripped_url = dest_url.trim_end_matches(".md").to_string();
-          let dest_file_path = resolve_relative_path(file_path, &dest_url)
-            .to_string_lossy()
-            .replace('\\', "/");
-
-          // Check if the file is a Markdown file
-          let dest_content = fs::read_to_string(&dest_file_path)
-            .map_err(|err| format!("Failed to read file '{}': {}", dest_file_path, err))
-            .unwrap_or("".to_string());
-
-          // Extract metadata
-          let dest_metadata = markdown_to_metadata(&dest_content).unwrap();
-          let dest_public = dest_metadata.public.unwrap_or(false);
-          let dest_name = Path::new(&stripped_url)
-            .file_stem()
-            .and_then(|n| n.to_str())
-            .unwrap_or("")
-            .replace(" ", "%20");
+          let target = resolve_note_link(file_path, &dest_url, config);
+          let dest_public = target.public;
+          let dest_name = slug::normalize(
+            Path::new(&target.path)
+              .file_stem()
+              .and_then(|n| n.to_str())
+              .unwrap_or(""),
+          );
 
           links.push(Link {
             source: file_name.to_owned(),
             target: dest_name,
-            target_path: dest_file_path,
+            target_path: target.file_path,
             target_public: Some(dest_public),
+            target_vault: target.vault.clone(),
           });
 
           if !dest_public && !config.private.include {
@@ -102,10 +161,26 @@ pub fn markdown_to_html(
               title: CowStr::from("private file"),
             });
           }
+
+          let dest_url = match (link_style, &target.vault) {
+            (LinkStyle::Server, Some(vault)) => format!(
+              "/vaults/{}/files/{}",
+              slug::encode_path(vault),
+              slug::link_url(&target.path, config.slug_style)
+            ),
+            (LinkStyle::Server, None) => slug::link_url(&target.path, config.slug_style),
+            // Other vaults are not part of the export
+            (LinkStyle::Static, Some(_)) => "#".to_string(),
+            // The exported pages keep the paths of the vault
+            (LinkStyle::Static, None) => static_url(
+              &format!("{}.html", slug::encode_path(&target.path)),
+              &to_root,
+            ),
+          };
           return Event::Start(Tag::Link {
             id,
             link_type,
-            dest_url: CowStr::from(stripped_url),
+            dest_url: CowStr::from(dest_url),
             title,
           });
         }
@@ -116,11 +191,26 @@ pub fn markdown_to_html(
           title,
         })
       }
+      Tag::Image {
+        link_type,
+        dest_url,
+        title,
+        id,
+      } if link_style == LinkStyle::Static => Event::Start(Tag::Image {
+        link_type,
+        dest_url: CowStr::from(static_url(&dest_url, &to_root)),
+        title,
+        id,
+      }),
       _ => Event::Start(tag),
     },
     Event::End(tag) => match tag {
-      TagEnd::Link {} => {
+      TagEnd::Link => {
         in_link_tag = false;
+        if in_external_link {
+          in_external_link = false;
+          return Event::InlineHtml(CowStr::from("</a>"));
+        }
         Event::End(tag)
       }
       _ => Event::End(tag),
@@ -132,7 +222,7 @@ pub fn markdown_to_html(
         && !config.private.include
         && !links
           .last()
-          .map_or(false, |link| link.target_public.unwrap_or(false))
+          .is_some_and(|link| link.target_public.unwrap_or(false))
       {
         return Event::Text(CowStr::from(format!("{} {}", config.private.icon, s)));
       }
@@ -164,9 +254,409 @@ pub fn markdown_to_html(
   let mut html_output = String::new();
   push_html(&mut html_output, parser);
 
+  if config.sanitize.enabled {
+    html_output = sanitizer::sanitize_html(&html_output, &config.sanitize);
+  }
+
   Ok((html_output, metadata, links))
 }
 
+fn markdown_options() -> Options {
+  let mut options = Options::empty();
+  options.insert(Options::ENABLE_YAML_STYLE_METADATA_BLOCKS);
+  options.insert(Options::ENABLE_STRIKETHROUGH);
+  // Disabled since it only enable blockquote but doesn't support blockquote title and as limited types of callouts
+  // options.insert(Options::ENABLE_GFM);
+  options
+}
+
+/// The markdown of a note as readers get it: private sections removed, links to
+/// private notes and raw html redacted like in the html.
+/// The frontmatter is removed unless `frontmatter` is set.
+pub fn redacted_markdown(
+  file_path: &str,
+  source_markdown: &str,
+  config: &UserConfig,
+  frontmatter: bool,
+) -> anyhow::Result<String> {
+  let metadata = markdown_to_metadata(source_markdown)?;
+  let source_markdown = redact::private_sections(source_markdown, config.private.include);
+  let strip_raw_html = strips_raw_html(file_path, &metadata, config);
+
+  // Parts of the source to replace, in order
+  let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+  let mut private_link: Option<(Range<usize>, String)> = None;
+
+  for (event, range) in Parser::new_ext(&source_markdown, markdown_options()).into_offset_iter() {
+    match event {
+      Event::Start(Tag::MetadataBlock(_)) if !frontmatter => edits.push((range, String::new())),
+      Event::Html(_) | Event::InlineHtml(_) if strip_raw_html && private_link.is_none() => {
+        edits.push((range, String::new()))
+      }
+      Event::Start(Tag::Link { dest_url, .. })
+        if !config.private.include
+          && is_note_link(&dest_url)
+          && !resolve_note_link(file_path, &dest_url, config).public =>
+      {
+        private_link = Some((range, String::new()));
+      }
+      Event::Text(text) | Event::Code(text) => {
+        if let Some((_, link_text)) = private_link.as_mut() {
+          link_text.push_str(&text);
+        }
+      }
+      Event::End(TagEnd::Link) => {
+        if let Some((range, text)) = private_link.take() {
+          edits.push((range, format!("[{} {}](#)", config.private.icon, text)));
+        }
+      }
+      _ => {}
+    }
+  }
+
+  let mut markdown = String::with_capacity(source_markdown.len());
+  let mut position = 0;
+  for (range, replacement) in edits {
+    if range.start < position {
+      continue;
+    }
+    markdown.push_str(&source_markdown[position..range.start]);
+    markdown.push_str(&replacement);
+    position = range.end;
+  }
+  markdown.push_str(&source_markdown[position..]);
+
+  if frontmatter {
+    Ok(markdown)
+  } else {
+    Ok(markdown.trim_start().to_string())
+  }
+}
+
+/// The note as plain text, with the same redaction as the html
+pub fn markdown_to_text(
+  file_path: &str,
+  source_markdown: &str,
+  config: &UserConfig,
+) -> anyhow::Result<String> {
+  let markdown = redacted_markdown(file_path, source_markdown, config, false)?;
+
+  let mut text = String::new();
+  let end_block = |text: &mut String, newlines: &str| {
+    let trimmed = text.trim_end_matches('\n').len();
+    text.truncate(trimmed);
+    if !text.is_empty() {
+      text.push_str(newlines);
+    }
+  };
+
+  for event in Parser::new_ext(&markdown, markdown_options()) {
+    match event {
+      Event::Text(content) | Event::Code(content) => text.push_str(&content),
+      Event::SoftBreak | Event::HardBreak => text.push('\n'),
+      Event::Start(Tag::List(_)) if !text.is_empty() && !text.ends_with('\n') => text.push('\n'),
+      Event::Start(Tag::Item) => text.push_str("- "),
+      Event::End(TagEnd::Item) => end_block(&mut text, "\n"),
+      Event::End(
+        TagEnd::Paragraph
+        | TagEnd::Heading(_)
+        | TagEnd::CodeBlock
+        | TagEnd::BlockQuote(_)
+        | TagEnd::List(_),
+      ) => end_block(&mut text, "\n\n"),
+      _ => {}
+    }
+  }
+
+  Ok(text.trim_end().to_string())
+}
+
+/// Target of a link to another note
+struct NoteLinkTarget {
+  /// Decoded link to the note, without extension, ex: `../sub/My Note`.
+  /// Relative to the root of its vault for the links to another vault.
+  path: String,
+  file_path: String,
+  public: bool,
+  /// Set when the note is in another vault
+  vault: Option<String>,
+}
+
+/// Whether the link points to another note, ex: `sub/note.md`
+fn is_note_link(dest_url: &str) -> bool {
+  !dest_url.starts_with("http") && dest_url.ends_with(".md")
+}
+
+fn resolve_note_link(file_path: &str, dest_url: &str, config: &UserConfig) -> NoteLinkTarget {
+  // Links to another vault are resolved against its root and privacy settings
+  let target_vault = dest_url
+    .strip_prefix(VAULT_LINK_PREFIX)
+    .and_then(|rest| rest.split_once('/'))
+    .and_then(|(name, path)| Some((config.vault(name)?, path)));
+
+  let (path, dest_file_path, dest_root, dest_private) = match &target_vault {
+    Some((vault, path)) => {
+      let path = slug::decode_path(path);
+      (
+        path.trim_end_matches(".md").to_string(),
+        Path::new(&vault.root_path).join(&path),
+        vault.root_path.as_str(),
+        &vault.private,
+      )
+    }
+    None => {
+      let path = slug::decode_path(dest_url);
+      (
+        path.trim_end_matches(".md").to_string(),
+        resolve_relative_path(file_path, &path),
+        config.root_path.as_str(),
+        &config.private,
+      )
+    }
+  };
+  let dest_file_path = dest_file_path.to_string_lossy().replace('\\', "/");
+
+  // Check if the file is a Markdown file
+  let dest_content = fs::read_to_string(&dest_file_path)
+    .map_err(|err| format!("Failed to read file '{}': {}", dest_file_path, err))
+    .unwrap_or("".to_string());
+
+  // Extract metadata
+  let dest_metadata = markdown_to_metadata(&dest_content).unwrap();
+  let public = policy::is_public(
+    dest_private,
+    &vault_relative_path(&dest_file_path, dest_root),
+    &dest_metadata,
+  );
+
+  NoteLinkTarget {
+    path,
+    file_path: dest_file_path,
+    public,
+    vault: target_vault.map(|(vault, _)| vault.name),
+  }
+}
+
+/// Whether raw html is removed from the note
+fn strips_raw_html(file_path: &str, metadata: &Metadata, config: &UserConfig) -> bool {
+  match config.sanitize.raw_html {
+    RawHtml::Keep => false,
+    RawHtml::StripPublic => policy::is_public(
+      &config.private,
+      &vault_relative_path(file_path, &config.root_path),
+      metadata,
+    ),
+    RawHtml::Strip => true,
+  }
+}
+
+/// Make a vault absolute url relative to the current page
+fn static_url(url: &str, to_root: &str) -> String {
+  match url.strip_prefix('/') {
+    Some(path) if !url.starts_with("//") => format!("{}{}", to_root, path),
+    _ => url.to_string(),
+  }
+}
+
+/// Local files referenced by the images and links of a note, other than notes,
+/// relative to the vault root.
+///
+/// Private sections are skipped unless private notes are included.
+pub fn markdown_assets(file_path: &str, source_markdown: &str, config: &UserConfig) -> Vec<String> {
+  let source_markdown = redact::private_sections(source_markdown, config.private.include);
+
+  let mut options = Options::empty();
+  options.insert(Options::ENABLE_YAML_STYLE_METADATA_BLOCKS);
+
+  let mut assets: Vec<String> = Vec::new();
+  for event in Parser::new_ext(&source_markdown, options) {
+    let dest_url = match event {
+      Event::Start(Tag::Image { dest_url, .. }) | Event::Start(Tag::Link { dest_url, .. }) => {
+        dest_url
+      }
+      _ => continue,
+    };
+
+    // Drop the query and fragment, the asset is the file itself
+    let dest_url = dest_url.split(['#', '?']).next().unwrap_or_default();
+    if dest_url.is_empty()
+      || dest_url.contains(':')
+      || dest_url.starts_with("//")
+      || dest_url.ends_with(".md")
+    {
+      continue;
+    }
+
+    let Some(asset) = asset_path(file_path, dest_url, config) else {
+      continue;
+    };
+    if !assets.contains(&asset) {
+      assets.push(asset);
+    }
+  }
+  assets
+}
+
+/// Local files referenced only from the private sections of a note, whatever `private.include`
+pub fn private_assets(file_path: &str, source_markdown: &str, config: &UserConfig) -> Vec<String> {
+  let mut public_config = config.clone();
+  public_config.private.include = false;
+  let public = markdown_assets(file_path, source_markdown, &public_config);
+
+  let mut private_config = config.clone();
+  private_config.private.include = true;
+  markdown_assets(file_path, source_markdown, &private_config)
+    .into_iter()
+    .filter(|asset| !public.contains(asset))
+    .collect()
+}
+
+/// Path relative to the vault root of a local file linked from a note,
+/// `None` when it points outside of the vault, ex: `/../secret.txt`
+fn asset_path(file_path: &str, dest_url: &str, config: &UserConfig) -> Option<String> {
+  let dest_url = slug::decode_path(dest_url);
+  let asset = match dest_url.strip_prefix('/') {
+    Some(path) => Path::new(&config.root_path).join(path),
+    None => resolve_relative_path(file_path, &dest_url),
+  };
+  let relative = asset.strip_prefix(&config.root_path).ok()?;
+  if relative
+    .components()
+    .any(|component| !matches!(component, Component::Normal(_)))
+  {
+    return None;
+  }
+  Some(vault_relative_path(
+    &asset.to_string_lossy(),
+    &config.root_path,
+  ))
+}
+
+/// Text of the first paragraph and the first image of the public part of a note,
+/// to describe it in previews.
+///
+/// Local images are relative to the vault root, other urls are kept as is.
+pub fn markdown_preview(
+  file_path: &str,
+  source_markdown: &str,
+  config: &UserConfig,
+) -> anyhow::Result<(Option<String>, Option<String>)> {
+  let mut public_config = config.clone();
+  public_config.private.include = false;
+  let markdown = redacted_markdown(file_path, source_markdown, &public_config, false)?;
+
+  let mut paragraph: Option<String> = None;
+  let mut current: Option<String> = None;
+  let mut image: Option<String> = None;
+  let mut in_image = false;
+  for event in Parser::new_ext(&markdown, markdown_options()) {
+    match event {
+      Event::Start(Tag::Paragraph) if paragraph.is_none() => current = Some(String::new()),
+      Event::End(TagEnd::Paragraph) => {
+        let text = current.take().unwrap_or_default();
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if paragraph.is_none() && !text.is_empty() {
+          paragraph = Some(text);
+        }
+      }
+      Event::Start(Tag::Image { dest_url, .. }) => {
+        in_image = true;
+        if image.is_none() {
+          let dest_url = dest_url.split(['#', '?']).next().unwrap_or_default();
+          image = if dest_url.contains(':') || dest_url.starts_with("//") {
+            Some(dest_url.to_string())
+          } else {
+            asset_path(file_path, dest_url, config)
+          };
+        }
+      }
+      Event::End(TagEnd::Image) => in_image = false,
+      // The alt text of images is not part of the description
+      Event::Text(text) | Event::Code(text) if !in_image => {
+        if let Some(current) = current.as_mut() {
+          current.push_str(&text);
+        }
+      }
+      Event::SoftBreak | Event::HardBreak => {
+        if let Some(current) = current.as_mut() {
+          current.push(' ');
+        }
+      }
+      _ => {}
+    }
+  }
+
+  Ok((paragraph, image.filter(|image| !image.is_empty())))
+}
+
+/// Headings of a note, private sections are skipped unless `include_private` is set
+pub fn markdown_outline(source_markdown: &str, include_private: bool) -> Vec<Heading> {
+  let source_markdown = redact::private_sections(source_markdown, include_private);
+
+  let mut options = Options::empty();
+  options.insert(Options::ENABLE_YAML_STYLE_METADATA_BLOCKS);
+  options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
+
+  let mut outline = Vec::new();
+  let mut current: Option<Heading> = None;
+  for event in Parser::new_ext(&source_markdown, options) {
+    match event {
+      Event::Start(Tag::Heading { level, .. }) => {
+        current = Some(Heading {
+          level: level as u8,
+          text: String::new(),
+        });
+      }
+      Event::Text(text) | Event::Code(text) => {
+        if let Some(heading) = current.as_mut() {
+          heading.text.push_str(&text);
+        }
+      }
+      Event::End(TagEnd::Heading(_)) => {
+        if let Some(heading) = current.take() {
+          outline.push(Heading {
+            text: heading.text.trim().to_string(),
+            ..heading
+          });
+        }
+      }
+      _ => {}
+    }
+  }
+  outline
+}
+
+/// Whether the url points outside of the vault
+pub(crate) fn is_external_url(url: &str) -> bool {
+  url.starts_with("http://") || url.starts_with("https://") || url.starts_with("//")
+}
+
+/// Build the opening tag of an external link with its `rel` attribute
+fn external_link_open_tag(dest_url: &str, title: &str, rel: &str) -> String {
+  let mut tag = String::from("<a href=\"");
+  escape_href(&mut tag, dest_url).unwrap();
+  if !title.is_empty() {
+    tag.push_str("\" title=\"");
+    escape_html(&mut tag, title).unwrap();
+  }
+  tag.push_str("\" rel=\"");
+  escape_html(&mut tag, rel).unwrap();
+  tag.push_str("\">");
+  tag
+}
+
+/// Path of a file relative to the vault root, with forward slashes
+pub fn vault_relative_path(file_path: &str, root_path: &str) -> String {
+  let path = Path::new(file_path);
+  path
+    .strip_prefix(root_path)
+    .unwrap_or(path)
+    .to_string_lossy()
+    .replace('\\', "/")
+    .trim_start_matches('/')
+    .to_string()
+}
+
 fn resolve_relative_path(base_file: &str, relative_path: &str) -> PathBuf {
   // Normalize paths to forward slashes
   let base_path = PathBuf::from(base_file.replace('\\', "/"));
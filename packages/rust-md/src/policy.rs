@@ -0,0 +1,134 @@
+use crate::{
+  config::{PrivateConfig, VisibilityRule},
+  parser::Metadata,
+};
+use globset::GlobBuilder;
+use serde::{Deserialize, Serialize};
+use serde_yaml_ng::Value;
+
+/// Result of the visibility policy for a note
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Visibility {
+  pub public: bool,
+  /// Index of the rule that decided, `None` when the frontmatter did
+  pub rule: Option<usize>,
+  pub rule_name: Option<String>,
+  pub reason: String,
+}
+
+/// Decide whether a note is public.
+///
+/// Rules are evaluated in order and the first matching rule wins,
+/// when none match the `public` field of the frontmatter is used.
+/// `path` is relative to the vault root, ex: `blog/post.md`
+pub fn evaluate(config: &PrivateConfig, path: &str, metadata: &Metadata) -> Visibility {
+  for (index, rule) in config.rules.iter().enumerate() {
+    if rule_matches(rule, path, metadata) {
+      return Visibility {
+        public: rule.public,
+        rule: Some(index),
+        rule_name: rule.name.clone(),
+        reason: format!(
+          "matched rule #{}{}: {}",
+          index,
+          rule
+            .name
+            .as_ref()
+            .map(|name| format!(" '{}'", name))
+            .unwrap_or_default(),
+          describe_rule(rule)
+        ),
+      };
+    }
+  }
+
+  let public = metadata.public.unwrap_or(false);
+  Visibility {
+    public,
+    rule: None,
+    rule_name: None,
+    reason: if public {
+      "no rule matched, frontmatter has `public: true`".to_string()
+    } else {
+      "no rule matched and frontmatter does not have `public: true`".to_string()
+    },
+  }
+}
+
+/// Shorthand for `evaluate(..).public`
+pub fn is_public(config: &PrivateConfig, path: &str, metadata: &Metadata) -> bool {
+  evaluate(config, path, metadata).public
+}
+
+/// Every condition of the rule must match
+fn rule_matches(rule: &VisibilityRule, path: &str, metadata: &Metadata) -> bool {
+  if let Some(pattern) = &rule.path {
+    let matches = GlobBuilder::new(pattern)
+      .literal_separator(true)
+      .build()
+      .map(|glob| glob.compile_matcher().is_match(path))
+      .unwrap_or_else(|err| {
+        log::warn!("Invalid visibility rule path '{}': {}", pattern, err);
+        false
+      });
+    if !matches {
+      return false;
+    }
+  }
+
+  if let Some(r#type) = &rule.r#type {
+    if metadata.r#type.as_ref() != Some(r#type) {
+      return false;
+    }
+  }
+
+  if let Some(tags) = &rule.tags {
+    let note_tags = metadata.tags.as_deref().unwrap_or_default();
+    if !tags.iter().any(|tag| note_tags.contains(tag)) {
+      return false;
+    }
+  }
+
+  if !rule.fields.is_empty() {
+    let frontmatter = serde_yaml_ng::to_value(metadata).unwrap_or(Value::Null);
+    for (field, expected) in &rule.fields {
+      let value = frontmatter.get(field).unwrap_or(&Value::Null);
+      let matches = match value {
+        Value::Sequence(values) if !expected.is_sequence() => values.contains(expected),
+        _ => value == expected,
+      };
+      if !matches {
+        return false;
+      }
+    }
+  }
+
+  true
+}
+
+/// Human readable summary of the rule conditions
+fn describe_rule(rule: &VisibilityRule) -> String {
+  let mut conditions = Vec::new();
+  if let Some(path) = &rule.path {
+    conditions.push(format!("path '{}'", path));
+  }
+  if let Some(r#type) = &rule.r#type {
+    conditions.push(format!("type '{}'", r#type));
+  }
+  if let Some(tags) = &rule.tags {
+    conditions.push(format!("tags [{}]", tags.join(", ")));
+  }
+  for (field, value) in &rule.fields {
+    let value = serde_yaml_ng::to_string(value).unwrap_or_default();
+    conditions.push(format!("{} = {}", field, value.trim()));
+  }
+  if conditions.is_empty() {
+    conditions.push("any note".to_string());
+  }
+
+  format!(
+    "{} -> {}",
+    conditions.join(" and "),
+    if rule.public { "public" } else { "private" }
+  )
+}
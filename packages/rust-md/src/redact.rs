@@ -0,0 +1,229 @@
+/// Marker wrapping a private block, `%%/private%%` can also close it
+const PRIVATE_FENCE: &str = "%%private%%";
+const PRIVATE_FENCE_END: &str = "%%/private%%";
+
+/// Kind of private section currently being read
+enum Section {
+  /// Between two `%%private%%` lines
+  Fence,
+  /// A `> [!private]` callout, ends at the first blank line
+  Callout,
+  /// A heading marked `{private}`, ends at the next heading of the same level or above
+  Heading(usize),
+}
+
+/// Remove the private sections of a note before it gets parsed.
+///
+/// When `keep` is true only the markers are removed, so the content
+/// renders like any other block.
+pub fn private_sections(source: &str, keep: bool) -> String {
+  let mut output = String::with_capacity(source.len());
+  let mut section: Option<Section> = None;
+  // Markers inside code blocks are ignored
+  let mut code_fence: Option<&str> = None;
+
+  for line in source.split_inclusive('\n') {
+    let trimmed = line.trim();
+
+    if let Some(fence) = code_fence {
+      if trimmed.starts_with(fence) && trimmed.trim_start_matches(&fence[..1]).is_empty() {
+        code_fence = None;
+      }
+      if section.is_none() || keep {
+        output.push_str(line);
+      }
+      continue;
+    }
+
+    if let Some(fence) = code_fence_marker(trimmed) {
+      code_fence = Some(fence);
+      if section.is_none() || keep {
+        output.push_str(line);
+      }
+      continue;
+    }
+
+    match section {
+      Some(Section::Fence) => {
+        if trimmed == PRIVATE_FENCE || trimmed == PRIVATE_FENCE_END {
+          section = None;
+        } else if keep {
+          output.push_str(line);
+        }
+        continue;
+      }
+      Some(Section::Callout) => {
+        if trimmed.is_empty() {
+          section = None;
+          output.push_str(line);
+        } else if keep {
+          output.push_str(line);
+        }
+        continue;
+      }
+      Some(Section::Heading(level)) => match heading_level(line) {
+        Some(next_level) if next_level <= level => section = None,
+        _ => {
+          if keep {
+            output.push_str(line);
+          }
+          continue;
+        }
+      },
+      None => {}
+    }
+
+    if trimmed == PRIVATE_FENCE {
+      section = Some(Section::Fence);
+    } else if is_private_callout(line) {
+      section = Some(Section::Callout);
+      if keep {
+        output.push_str(line);
+      }
+    } else if let Some((level, heading)) = private_heading(line) {
+      section = Some(Section::Heading(level));
+      if keep {
+        output.push_str(heading);
+        output.push('\n');
+      }
+    } else {
+      output.push_str(line);
+    }
+  }
+
+  output
+}
+
+/// Return the marker of a line opening a code block
+fn code_fence_marker(trimmed: &str) -> Option<&'static str> {
+  if trimmed.starts_with("```") {
+    Some("```")
+  } else if trimmed.starts_with("~~~") {
+    Some("~~~")
+  } else {
+    None
+  }
+}
+
+/// Level of an ATX heading line
+fn heading_level(line: &str) -> Option<usize> {
+  let indent = line.len() - line.trim_start_matches(' ').len();
+  if indent > 3 {
+    return None;
+  }
+  let line = &line[indent..];
+  let level = line.chars().take_while(|c| *c == '#').count();
+  let rest = &line[level..];
+  if (1..=6).contains(&level) && (rest.is_empty() || rest.starts_with([' ', '\t', '\n', '\r'])) {
+    Some(level)
+  } else {
+    None
+  }
+}
+
+/// `> [!private]` callout, folding markers (`-`/`+`) and titles are allowed
+fn is_private_callout(line: &str) -> bool {
+  line
+    .trim_start()
+    .strip_prefix('>')
+    .map(|quote| quote.trim_start().to_lowercase().starts_with("[!private]"))
+    .unwrap_or(false)
+}
+
+/// Heading ending with a `{private}` or `{.private}` attribute,
+/// returns its level and the heading without the attribute
+fn private_heading(line: &str) -> Option<(usize, &str)> {
+  let level = heading_level(line)?;
+  let trimmed = line.trim_end();
+  let attributes_start = trimmed.rfind('{')?;
+  let attributes = trimmed[attributes_start..]
+    .strip_prefix('{')?
+    .strip_suffix('}')?;
+
+  if attributes
+    .split_whitespace()
+    .any(|attribute| attribute == "private" || attribute == ".private")
+  {
+    Some((level, trimmed[..attributes_start].trim_end()))
+  } else {
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::private_sections;
+  use crate::{config::folder_config, parser::render_markdown, LinkStyle};
+
+  #[test]
+  fn unclosed_fence_hides_the_rest_of_the_note() {
+    let source = "public\n%%private%%\nsecret\n\n# Heading\nstill secret\n";
+    assert_eq!(private_sections(source, false), "public\n");
+    assert_eq!(
+      private_sections(source, true),
+      "public\nsecret\n\n# Heading\nstill secret\n"
+    );
+  }
+
+  #[test]
+  fn fence_inside_a_code_block_is_kept() {
+    let source = "```md\n%%private%%\nexample\n%%private%%\n```\nafter\n";
+    assert_eq!(private_sections(source, false), source);
+
+    let source = "~~~\n%%private%%\n~~~\nvisible\n";
+    assert_eq!(private_sections(source, false), source);
+  }
+
+  #[test]
+  fn callout_with_a_fold_marker() {
+    for marker in ["[!private]-", "[!private]+ Title", "[!PRIVATE]"] {
+      let source = format!("public\n> {}\n> secret\n\nafter\n", marker);
+      assert_eq!(
+        private_sections(&source, false),
+        "public\n\nafter\n",
+        "{}",
+        marker
+      );
+    }
+  }
+
+  #[test]
+  fn private_heading_ends_at_a_heading_of_the_same_level_or_above() {
+    let source = "## Notes {private}\nsecret\n### Deeper\nstill secret\n## Next\npublic\n";
+    assert_eq!(private_sections(source, false), "## Next\npublic\n");
+
+    let source = "## Notes {.private}\nsecret\n# Top\npublic\n";
+    assert_eq!(private_sections(source, false), "# Top\npublic\n");
+
+    let source = "## Notes {private}\nsecret\n";
+    assert_eq!(private_sections(source, true), "## Notes\nsecret\n");
+  }
+
+  #[test]
+  fn links_of_private_sections_are_not_listed() {
+    let root = tempfile::tempdir().unwrap();
+    let mut config = folder_config(&root.path().to_string_lossy()).unwrap();
+    let file_path = root
+      .path()
+      .join("note.md")
+      .to_string_lossy()
+      .replace('\\', "/");
+    let source = "[public](public.md)\n\n%%private%%\n[fenced](fenced.md)\n%%private%%\n\n\
+      > [!private]\n> [callout](callout.md)\n\n## Notes {private}\n[heading](heading.md)\n";
+
+    let render = |include: bool, config: &mut crate::UserConfig| {
+      config.private.include = include;
+      let (html, _, links) =
+        render_markdown(&file_path, "note", source, config, LinkStyle::Server).unwrap();
+      let targets: Vec<String> = links.into_iter().map(|link| link.target).collect();
+      (html, targets)
+    };
+
+    let (html, public) = render(false, &mut config);
+    assert_eq!(public, ["public"]);
+    assert!(!html.contains("fenced") && !html.contains("callout") && !html.contains("heading"));
+
+    let (_, all) = render(true, &mut config);
+    assert_eq!(all, ["public", "fenced", "callout", "heading"]);
+  }
+}
@@ -0,0 +1,251 @@
+use crate::{
+  cli::{DEFAULT_BIND, DEFAULT_PORT},
+  config::{self, load_config, UserConfig},
+  service::{drop_ignored_notes, find_all_notes, find_new_notes},
+  share::Shares,
+  slug,
+  templates::Templates,
+  watcher::IndexEvent,
+  Vault,
+};
+use anyhow::Result;
+use colored::Colorize;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+/// Watch the config file and apply its changes to every vault without restarting
+pub async fn watch_config(
+  config_path: &str,
+  root_override: Option<String>,
+  mut config: UserConfig,
+  vaults: Vec<Vault>,
+) -> Result<()> {
+  let config_path = PathBuf::from(config_path);
+  let file_name = config_path
+    .file_name()
+    .ok_or_else(|| anyhow::anyhow!("Invalid config path '{}'", config_path.display()))?
+    .to_owned();
+
+  let (tx, mut rx) = mpsc::unbounded_channel::<notify::Result<Event>>();
+  let mut watcher = notify::recommended_watcher(move |res| {
+    let _ = tx.send(res);
+  })?;
+
+  // Editors often replace the file instead of writing to it,
+  // so the parent folder is watched
+  let config_dir = match config_path.parent() {
+    Some(dir) if !dir.as_os_str().is_empty() => dir,
+    _ => Path::new("."),
+  };
+  watcher.watch(config_dir, RecursiveMode::NonRecursive)?;
+
+  while let Some(res) = rx.recv().await {
+    match res {
+      Ok(event) => {
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+          || !event
+            .paths
+            .iter()
+            .any(|path| path.file_name() == Some(file_name.as_os_str()))
+        {
+          continue;
+        }
+
+        apply_config(&config_path, root_override.as_deref(), &mut config, &vaults).await;
+      }
+      Err(e) => log::error!("config watch error: {:?}", e),
+    }
+  }
+
+  Ok(())
+}
+
+/// Load the config file again and update the index of each vault accordingly
+async fn apply_config(
+  config_path: &Path,
+  root_override: Option<&str>,
+  old_config: &mut UserConfig,
+  vaults: &[Vault],
+) {
+  let mut new_config = match load_config(&config_path.to_string_lossy(), root_override) {
+    Ok(config) => config,
+    Err(err) => {
+      log::warn!(
+        "{} × Invalid config, keeping the current one:\n{}",
+        "[config]".yellow(),
+        err
+      );
+      return;
+    }
+  };
+
+  keep_vaults(old_config, &mut new_config);
+
+  if *old_config == new_config {
+    return;
+  }
+
+  // Tokens are redacted, a changed token is not listed
+  log::info!("{} » Config changed:", "[config]".yellow());
+  for change in config::diff(old_config, &new_config) {
+    log::info!("    {}", change.bold());
+  }
+
+  // Links to another vault depend on its privacy settings
+  let privacy = |config: &UserConfig| -> Vec<_> {
+    let mut privacy = vec![config.private.clone()];
+    privacy.extend(config.vaults.iter().map(|vault| vault.private.clone()));
+    privacy
+  };
+  let privacy_changed = privacy(old_config) != privacy(&new_config);
+
+  *old_config = new_config.clone();
+
+  for (name, vault_config) in new_config.vault_configs() {
+    let Some(state) = vaults.iter().find(|state| state.name == name) else {
+      continue;
+    };
+    let previous = std::mem::replace(&mut *state.config.write().await, vault_config.clone());
+
+    if vault_config.templates != previous.templates {
+      *state.templates.write().await = Templates::load(&vault_config);
+    }
+    if vault_config.auth.share_secret != previous.auth.share_secret {
+      *state.shares.lock().await = Shares::load(&name, &vault_config);
+    }
+
+    // Visibility and links depend on the private and sanitize settings, the typed
+    // fields on the schemas, the dates on their formats and the urls on the slug style
+    if privacy_changed
+      || vault_config.sanitize != previous.sanitize
+      || vault_config.schemas != previous.schemas
+      || vault_config.dates != previous.dates
+      || vault_config.slug_style != previous.slug_style
+    {
+      reindex(state, &vault_config, true).await;
+    } else if vault_config.ignore != previous.ignore {
+      reindex(state, &vault_config, false).await;
+    }
+  }
+}
+
+/// Vaults are indexed and watched at startup, so their roots, the list of
+/// vaults and where the webserver listens can only change with a restart
+fn keep_vaults(old_config: &UserConfig, new_config: &mut UserConfig) {
+  let restart_required = |field: &str, kept: &str| {
+    log::warn!(
+      "{} × Changing {} requires a restart, keeping '{}'",
+      "[config]".yellow(),
+      field.bold(),
+      kept
+    );
+  };
+
+  if new_config.root_path != old_config.root_path {
+    restart_required("root_path", &old_config.root_path);
+    new_config.root_path = old_config.root_path.clone();
+  }
+
+  if new_config.server != old_config.server {
+    let server = &old_config.server;
+    let kept = server.unix_socket.clone().unwrap_or_else(|| {
+      format!(
+        "{}:{}",
+        server.host.as_deref().unwrap_or(DEFAULT_BIND),
+        server.port.unwrap_or(DEFAULT_PORT)
+      )
+    });
+    restart_required("server", &kept);
+    new_config.server = old_config.server.clone();
+  }
+
+  let mut vaults = Vec::new();
+  for (index, old_vault) in old_config.vaults.iter().enumerate() {
+    match new_config
+      .vaults
+      .iter()
+      .find(|vault| vault.name == old_vault.name)
+    {
+      Some(vault) => {
+        let mut vault = vault.clone();
+        if vault.root_path != old_vault.root_path {
+          restart_required(
+            &format!("vaults[{}].root_path", index),
+            &old_vault.root_path,
+          );
+          vault.root_path = old_vault.root_path.clone();
+        }
+        vaults.push(vault);
+      }
+      None => {
+        restart_required("vaults", &old_vault.name);
+        vaults.push(old_vault.clone());
+      }
+    }
+  }
+
+  for vault in &new_config.vaults {
+    if !vaults.iter().any(|kept| kept.name == vault.name) {
+      log::warn!(
+        "{} × Adding the vault {} requires a restart",
+        "[config]".yellow(),
+        vault.name.bold()
+      );
+    }
+  }
+
+  new_config.vaults = vaults;
+}
+
+/// Update the index after the ignore patterns or the rendering settings changed.
+///
+/// Ignored notes are dropped, then every note is parsed again when `full`
+/// is true, otherwise only the notes that are not indexed yet.
+pub async fn reindex(state: &Vault, config: &UserConfig, full: bool) {
+  let start = std::time::Instant::now();
+  let notes = state.notes.clone();
+
+  for path in drop_ignored_notes(notes.clone(), config).await {
+    log::info!(
+      "{} × Dropped: {}",
+      "[config]".yellow(),
+      path.bright_black().bold()
+    );
+  }
+
+  let before = notes.lock().await.len();
+  let root_path = PathBuf::from(&config.root_path);
+  let result = if full {
+    find_all_notes(&root_path, notes.clone(), config).await
+  } else {
+    find_new_notes(&root_path, notes.clone(), config).await
+  };
+  if let Err(err) = result {
+    log::warn!(
+      "{} × Failed to update the index: {}",
+      "[config]".yellow(),
+      err
+    );
+  }
+
+  // Same order as the watcher, the index then the ids
+  {
+    let mut notes = notes.lock().await;
+    let mut ids = state.ids.lock().await;
+    ids.assign(&mut notes);
+    ids.save();
+    slug::report_collisions(&notes);
+  }
+  state.index.write().await.indexed(start);
+  let _ = state.events.send(IndexEvent::Reindexed);
+
+  let after = notes.lock().await.len();
+  log::info!(
+    "{} » Index updated: {} note(s), {} new in {:?}",
+    "[config]".yellow(),
+    after,
+    after.saturating_sub(before),
+    start.elapsed()
+  );
+}
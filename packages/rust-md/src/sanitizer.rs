@@ -0,0 +1,202 @@
+use crate::{config::SanitizeConfig, parser::is_external_url};
+use std::collections::HashSet;
+
+/// Tags whose content is always removed, they can't be allowed
+const CLEAN_CONTENT_TAGS: [&str; 2] = ["script", "style"];
+
+/// Clean the rendered html using the allowlist from the config
+pub fn sanitize_html(html: &str, config: &SanitizeConfig) -> String {
+  let mut builder = ammonia::Builder::default();
+
+  if let Some(tags) = &config.tags {
+    builder.tags(
+      tags
+        .iter()
+        .map(String::as_str)
+        .filter(|tag| !CLEAN_CONTENT_TAGS.contains(tag))
+        .collect(),
+    );
+  }
+
+  match &config.attributes {
+    Some(attributes) => {
+      builder.tag_attributes(
+        attributes
+          .iter()
+          .filter(|(tag, _)| !CLEAN_CONTENT_TAGS.contains(&tag.as_str()))
+          .map(|(tag, attrs)| {
+            (
+              tag.as_str(),
+              attrs.iter().map(String::as_str).collect::<HashSet<_>>(),
+            )
+          })
+          .collect(),
+      );
+    }
+    None => {
+      // Keep the language of code blocks
+      builder.add_tag_attributes("code", &["class"]);
+    }
+  }
+
+  builder
+    .add_tag_attributes("a", &["rel"])
+    .link_rel(None)
+    .url_schemes(config.url_schemes.iter().map(String::as_str).collect());
+
+  let Some(rel) = &config.link_rel else {
+    return builder.clean(html).to_string();
+  };
+  // The `rel` of external links is set below, raw html can't weaken it
+  builder.attribute_filter(|element, attribute, value| match (element, attribute) {
+    ("a", "rel") => None,
+    _ => Some(value.into()),
+  });
+  external_links_rel(&builder.clean(html).to_string(), rel)
+}
+
+/// Add `rel` to the links of sanitized html whose href is external
+fn external_links_rel(html: &str, rel: &str) -> String {
+  let rel = format!(
+    " rel=\"{}\"",
+    rel.replace('&', "&amp;").replace('"', "&quot;")
+  );
+  let mut output = String::with_capacity(html.len());
+  let mut rest = html;
+
+  while let Some(start) = rest.find("<a ") {
+    output.push_str(&rest[..start]);
+    rest = &rest[start..];
+    let end = tag_end(rest);
+    let tag = &rest[..end];
+    // Attribute values are quoted and their quotes escaped by the sanitizer
+    let external = tag
+      .split_once(" href=\"")
+      .and_then(|(_, value)| value.split('"').next())
+      .is_some_and(is_external_url);
+
+    output.push_str(tag);
+    if external {
+      output.push_str(&rel);
+    }
+    rest = &rest[end..];
+  }
+
+  output.push_str(rest);
+  output
+}
+
+/// Position of the `>` closing the tag at the start of `html`
+fn tag_end(html: &str) -> usize {
+  let mut quoted = false;
+  for (i, c) in html.char_indices() {
+    match c {
+      '"' => quoted = !quoted,
+      '>' if !quoted => return i,
+      _ => {}
+    }
+  }
+  html.len()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::sanitize_html;
+  use crate::{
+    config::{folder_config, RawHtml, SanitizeConfig, UserConfig},
+    parser::render_markdown,
+    LinkStyle,
+  };
+  use tempfile::TempDir;
+
+  fn render(source: &str, config: &UserConfig) -> String {
+    let file_path = format!("{}/note.md", config.root_path);
+    render_markdown(&file_path, "note", source, config, LinkStyle::Server)
+      .unwrap()
+      .0
+  }
+
+  /// The config of a temporary vault, kept until the dir is dropped
+  fn config() -> (UserConfig, TempDir) {
+    let dir = tempfile::tempdir().unwrap();
+    let config = folder_config(&dir.path().to_string_lossy()).unwrap();
+    (config, dir)
+  }
+
+  #[test]
+  fn scripts_and_javascript_links_are_removed() {
+    let html = "<p><a href=\"javascript:alert(1)\">link</a></p><script>alert(2)</script>";
+    let clean = sanitize_html(html, &SanitizeConfig::default());
+    assert!(!clean.contains("javascript:"), "{}", clean);
+    assert!(
+      !clean.contains("<script") && !clean.contains("alert(2)"),
+      "{}",
+      clean
+    );
+    assert!(clean.contains(">link</a>"), "{}", clean);
+
+    let html = render(
+      "[link](javascript:alert(1))\n\n<script>alert(2)</script>\n",
+      &config().0,
+    );
+    assert!(
+      !html.contains("javascript:") && !html.contains("<script"),
+      "{}",
+      html
+    );
+  }
+
+  #[test]
+  fn external_links_get_the_configured_rel() {
+    let html = render("[out](https://example.com) [in](other.md)\n", &config().0);
+    assert!(
+      html.contains("<a href=\"https://example.com\" rel=\"noopener nofollow\">out</a>"),
+      "{}",
+      html
+    );
+    assert_eq!(html.matches("rel=").count(), 1, "{}", html);
+  }
+
+  #[test]
+  fn raw_html_links_get_the_configured_rel() {
+    let html = render(
+      "<a href=\"https://example.com\" title=\"a > b\">out</a>\n\
+       <a href=\"//example.com\" rel=\"opener\">bare</a>\n\
+       <a href=\"/local\" rel=\"opener\">in</a>\n",
+      &config().0,
+    );
+    assert!(
+      html.contains(
+        "<a href=\"https://example.com\" title=\"a &gt; b\" rel=\"noopener nofollow\">out</a>"
+      ),
+      "{}",
+      html
+    );
+    assert!(
+      html.contains("<a href=\"//example.com\" rel=\"noopener nofollow\">bare</a>"),
+      "{}",
+      html
+    );
+    assert!(html.contains("<a href=\"/local\">in</a>"), "{}", html);
+    assert!(!html.contains("opener\""), "{}", html);
+  }
+
+  #[test]
+  fn strip_public_removes_the_raw_html_of_public_notes_only() {
+    let (mut config, _dir) = config();
+    config.sanitize.raw_html = RawHtml::StripPublic;
+    let body = "text <kbd>key</kbd>\n\n<div>block</div>\n";
+
+    let public = render(&format!("---\npublic: true\n---\n{}", body), &config);
+    assert!(
+      !public.contains("<kbd>") && !public.contains("<div>"),
+      "{}",
+      public
+    );
+    assert!(public.contains("key"), "{}", public);
+
+    let private = render(body, &config);
+    assert!(private.contains("<kbd>key</kbd>"), "{}", private);
+    assert!(private.contains("<div>block</div>"), "{}", private);
+  }
+}
@@ -0,0 +1,130 @@
+use crate::{
+  config::{FieldSchema, FieldType, UserConfig},
+  dates,
+  parser::Metadata,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// A frontmatter field not matching the schema of the note type
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SchemaViolation {
+  pub field: String,
+  pub message: String,
+}
+
+/// Fields of a note checked against the schema of its type
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TypedFields {
+  /// Values coerced to the types of the schema, defaults included
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub fields: BTreeMap<String, Value>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub violations: Vec<SchemaViolation>,
+}
+
+/// Check the frontmatter against the schema of the note type, if any
+pub fn check_metadata(metadata: &Metadata, config: &UserConfig) -> TypedFields {
+  let mut typed = TypedFields::default();
+  let Some(schema) = metadata
+    .r#type
+    .as_ref()
+    .and_then(|r#type| config.schemas.get(r#type))
+  else {
+    return typed;
+  };
+
+  let frontmatter = serde_json::to_value(metadata).unwrap_or_default();
+  for (name, field) in &schema.fields {
+    let value = frontmatter.get(name).filter(|value| !value.is_null());
+    let Some(value) = value.or(field.default.as_ref()) else {
+      if field.required {
+        typed.violations.push(SchemaViolation {
+          field: name.clone(),
+          message: "missing required field".to_string(),
+        });
+      }
+      continue;
+    };
+
+    match coerce(value, field, &config.dates.formats) {
+      Ok(value) => {
+        typed.fields.insert(name.clone(), value);
+      }
+      Err(message) => typed.violations.push(SchemaViolation {
+        field: name.clone(),
+        message,
+      }),
+    }
+  }
+
+  typed
+}
+
+/// Convert a value to the type of the field, ex: `"3"` to `3` for an integer,
+/// then check it is one of the allowed values
+pub fn coerce(value: &Value, field: &FieldSchema, formats: &[String]) -> Result<Value, String> {
+  let invalid = || {
+    format!(
+      "expected {}, got {}",
+      field.r#type.name(),
+      serde_json::to_string(value).unwrap_or_default()
+    )
+  };
+
+  let coerced = match (field.r#type, value) {
+    (FieldType::String, Value::String(_)) => value.clone(),
+    (FieldType::String, Value::Number(number)) => Value::String(number.to_string()),
+    (FieldType::String, Value::Bool(boolean)) => Value::String(boolean.to_string()),
+    (FieldType::Integer, Value::Number(number)) if number.is_i64() || number.is_u64() => {
+      value.clone()
+    }
+    (FieldType::Integer, Value::String(text)) => text
+      .trim()
+      .parse::<i64>()
+      .map(Value::from)
+      .map_err(|_| invalid())?,
+    (FieldType::Number, Value::Number(_)) => value.clone(),
+    (FieldType::Number, Value::String(text)) => text
+      .trim()
+      .parse::<f64>()
+      .ok()
+      .and_then(serde_json::Number::from_f64)
+      .map(Value::Number)
+      .ok_or_else(invalid)?,
+    (FieldType::Boolean, Value::Bool(_)) => value.clone(),
+    (FieldType::Boolean, Value::String(text)) => match text.trim().to_lowercase().as_str() {
+      "true" | "yes" => Value::Bool(true),
+      "false" | "no" => Value::Bool(false),
+      _ => return Err(invalid()),
+    },
+    (FieldType::Date, Value::String(text)) => dates::parse_date(text, formats)
+      .map(|date| Value::String(date.to_rfc3339()))
+      .ok_or_else(invalid)?,
+    (FieldType::List, Value::Array(_)) => value.clone(),
+    // A single value is a list of one item
+    (FieldType::List, Value::String(_) | Value::Number(_) | Value::Bool(_)) => {
+      Value::Array(vec![value.clone()])
+    }
+    _ => return Err(invalid()),
+  };
+
+  if let Some(allowed) = &field.r#enum {
+    let items = match &coerced {
+      Value::Array(items) => items.iter().collect(),
+      _ => vec![&coerced],
+    };
+    for item in items {
+      if !allowed.contains(item) {
+        return Err(format!(
+          "{} is not one of {}",
+          serde_json::to_string(item).unwrap_or_default(),
+          serde_json::to_string(allowed).unwrap_or_default()
+        ));
+      }
+    }
+  }
+
+  Ok(coerced)
+}
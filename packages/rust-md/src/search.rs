@@ -0,0 +1,81 @@
+use crate::app::Note;
+use chrono::{DateTime, FixedOffset};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::cmp::Reverse;
+
+/// Which notes to find, every field set must match
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct NoteFilter {
+  /// Notes having all of these tags
+  pub tags: Vec<String>,
+  pub r#type: Option<String>,
+  pub public: Option<bool>,
+  /// Notes updated or created at or after this date
+  pub after: Option<DateTime<FixedOffset>>,
+  /// Notes updated or created at or before this date
+  pub before: Option<DateTime<FixedOffset>>,
+  /// Searched in the name, title and content, whatever the case
+  pub text: Option<String>,
+}
+
+impl NoteFilter {
+  pub fn matches(&self, note: &Note) -> bool {
+    let metadata = &note.data.metadata;
+    let tags = metadata.tags.as_deref().unwrap_or_default();
+    let latest = note.data.dates.latest();
+    let text = self.text.as_ref().map(|text| text.to_lowercase());
+
+    self.tags.iter().all(|tag| tags.contains(tag))
+      && self
+        .r#type
+        .as_ref()
+        .is_none_or(|r#type| metadata.r#type.as_ref() == Some(r#type))
+      && self.public.is_none_or(|public| note.public == public)
+      && self
+        .after
+        .is_none_or(|after| latest.is_some_and(|date| date >= after))
+      && self
+        .before
+        .is_none_or(|before| latest.is_some_and(|date| date <= before))
+      && text.is_none_or(|text| {
+        note.name.to_lowercase().contains(&text)
+          || metadata
+            .title
+            .as_ref()
+            .is_some_and(|title| title.to_lowercase().contains(&text))
+          || note.content.to_lowercase().contains(&text)
+      })
+  }
+}
+
+/// Order of the notes found
+#[derive(Deserialize, ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteSort {
+  #[default]
+  Path,
+  /// Most recently created first
+  Created,
+  /// Most recently updated first
+  Updated,
+}
+
+/// Notes matching the filter, undated notes last when sorted by date
+pub fn search<'a>(
+  notes: impl IntoIterator<Item = &'a Note>,
+  filter: &NoteFilter,
+  sort: NoteSort,
+) -> Vec<&'a Note> {
+  let mut found: Vec<&Note> = notes
+    .into_iter()
+    .filter(|note| filter.matches(note))
+    .collect();
+  match sort {
+    NoteSort::Path => found.sort_by(|a, b| a.path.cmp(&b.path)),
+    NoteSort::Created => found.sort_by_key(|note| Reverse(note.data.dates.created)),
+    NoteSort::Updated => found.sort_by_key(|note| Reverse(note.data.dates.updated)),
+  }
+  found
+}
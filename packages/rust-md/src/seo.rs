@@ -0,0 +1,189 @@
+use crate::{
+  app::Note,
+  config::UserConfig,
+  parser::{self, Metadata},
+};
+use chrono::{DateTime, FixedOffset, SecondsFormat, Utc};
+use pulldown_cmark_escape::escape_html;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path};
+
+/// Longest description, in characters
+const DESCRIPTION_LENGTH: usize = 200;
+
+/// Preview of a note for search engines and social networks
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Seo {
+  pub title: String,
+  /// `summary` of the frontmatter, else the first paragraph of the public part
+  pub description: Option<String>,
+  /// First image, relative to the vault root for local files
+  pub image: Option<String>,
+}
+
+pub fn note_seo(
+  file_path: &str,
+  file_name: &str,
+  source_markdown: &str,
+  metadata: &Metadata,
+  config: &UserConfig,
+) -> Seo {
+  let (paragraph, image) =
+    parser::markdown_preview(file_path, source_markdown, config).unwrap_or_default();
+
+  Seo {
+    title: metadata
+      .title
+      .clone()
+      .unwrap_or_else(|| file_name.to_string()),
+    description: metadata
+      .summary
+      .clone()
+      .or(paragraph)
+      .map(|description| truncate(&description)),
+    image,
+  }
+}
+
+fn truncate(text: &str) -> String {
+  if text.chars().count() <= DESCRIPTION_LENGTH {
+    return text.to_string();
+  }
+  let mut truncated: String = text.chars().take(DESCRIPTION_LENGTH - 1).collect();
+  truncated.truncate(truncated.trim_end().len());
+  truncated.push('…');
+  truncated
+}
+
+/// OpenGraph and Twitter card fields of a page, with absolute urls
+#[derive(Serialize, Debug)]
+pub struct SeoMeta {
+  pub title: String,
+  pub description: Option<String>,
+  pub image: Option<String>,
+  pub url: String,
+  pub site_name: Option<String>,
+  /// `summary_large_image` when there is an image, else `summary`
+  pub card: &'static str,
+}
+
+/// `asset_url` gives the absolute url of a file relative to the vault root
+pub fn seo_meta(
+  note: &Note,
+  config: &UserConfig,
+  url: String,
+  asset_url: impl Fn(&str) -> String,
+) -> SeoMeta {
+  let seo = &note.data.seo;
+  let image = seo.image.as_ref().map(|image| {
+    if image.contains(':') || image.starts_with("//") {
+      image.clone()
+    } else {
+      asset_url(image)
+    }
+  });
+
+  SeoMeta {
+    title: seo.title.clone(),
+    description: seo.description.clone(),
+    card: if image.is_some() {
+      "summary_large_image"
+    } else {
+      "summary"
+    },
+    image,
+    url,
+    site_name: config.site.title.clone(),
+  }
+}
+
+/// `<meta>` tags of the page, for the `<head>`
+pub fn meta_tags(meta: &SeoMeta) -> String {
+  let mut tags = Vec::new();
+  let mut push = |attribute: &str, name: &str, value: &str| {
+    let mut escaped = String::new();
+    escape_html(&mut escaped, value).unwrap();
+    tags.push(format!(
+      "<meta {}=\"{}\" content=\"{}\">",
+      attribute, name, escaped
+    ));
+  };
+
+  push("property", "og:type", "article");
+  push("property", "og:title", &meta.title);
+  push("property", "og:url", &meta.url);
+  if let Some(site_name) = &meta.site_name {
+    push("property", "og:site_name", site_name);
+  }
+  if let Some(description) = &meta.description {
+    push("name", "description", description);
+    push("property", "og:description", description);
+  }
+  if let Some(image) = &meta.image {
+    push("property", "og:image", image);
+  }
+  push("name", "twitter:card", meta.card);
+  push("name", "twitter:title", &meta.title);
+  if let Some(description) = &meta.description {
+    push("name", "twitter:description", description);
+  }
+  if let Some(image) = &meta.image {
+    push("name", "twitter:image", image);
+  }
+
+  let mut html = tags.join("\n");
+  html.push('\n');
+  html
+}
+
+#[derive(Debug)]
+pub struct SitemapEntry {
+  pub url: String,
+  pub lastmod: Option<DateTime<FixedOffset>>,
+}
+
+/// Public notes of the vault, dated by their `updated` field, else by the file modification time.
+///
+/// `url` gives the absolute url of a note.
+pub fn sitemap_entries(
+  notes: &HashMap<String, Note>,
+  config: &UserConfig,
+  url: impl Fn(&Note) -> String,
+) -> Vec<SitemapEntry> {
+  let mut entries: Vec<SitemapEntry> = notes
+    .values()
+    .filter(|note| note.public)
+    .map(|note| SitemapEntry {
+      url: url(note),
+      lastmod: note.data.dates.updated.or_else(|| {
+        fs::metadata(Path::new(&config.root_path).join(note.relative_file_path()))
+          .and_then(|metadata| metadata.modified())
+          .ok()
+          .map(|modified| DateTime::<Utc>::from(modified).fixed_offset())
+      }),
+    })
+    .collect();
+  entries.sort_by(|a, b| a.url.cmp(&b.url));
+  entries
+}
+
+/// <https://www.sitemaps.org/protocol.html>
+pub fn render_sitemap(entries: &[SitemapEntry]) -> String {
+  let mut xml = String::from(
+    "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+  );
+  for entry in entries {
+    let mut url = String::new();
+    escape_html(&mut url, &entry.url).unwrap();
+    xml.push_str(&format!("<url>\n<loc>{}</loc>\n", url));
+    if let Some(lastmod) = entry.lastmod {
+      xml.push_str(&format!(
+        "<lastmod>{}</lastmod>\n",
+        lastmod.to_rfc3339_opts(SecondsFormat::Secs, true)
+      ));
+    }
+    xml.push_str("</url>\n");
+  }
+  xml.push_str("</urlset>\n");
+  xml
+}
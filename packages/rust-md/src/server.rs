@@ -0,0 +1,193 @@
+use crate::{
+  cli::{DEFAULT_BIND, DEFAULT_PORT},
+  config::{ServerConfig, TlsConfig},
+};
+use anyhow::{Context, Result};
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use colored::Colorize;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::{
+  collections::BTreeSet,
+  path::{Path, PathBuf},
+  time::Duration,
+};
+use tokio::sync::mpsc;
+
+/// Certificates are often written along with their key, the reload waits for both
+const TLS_RELOAD_DELAY: Duration = Duration::from_millis(200);
+
+/// A bound listener of the webserver
+pub enum Listener {
+  Tcp(tokio::net::TcpListener),
+  Tls {
+    listener: std::net::TcpListener,
+    rustls: RustlsConfig,
+    files: TlsConfig,
+  },
+  #[cfg(unix)]
+  Unix {
+    listener: tokio::net::UnixListener,
+    path: PathBuf,
+  },
+}
+
+impl Listener {
+  /// Bind the unix socket, else the host and port, with tls when configured
+  pub async fn bind(config: &ServerConfig) -> Result<Self> {
+    if let Some(path) = &config.unix_socket {
+      return bind_unix(Path::new(path));
+    }
+
+    let host = config.host.as_deref().unwrap_or(DEFAULT_BIND);
+    // `[::1]` is written like in urls
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = config.port.unwrap_or(DEFAULT_PORT);
+    let listener = tokio::net::TcpListener::bind((host, port))
+      .await
+      .with_context(|| format!("Failed to bind {}:{}", host, port))?;
+
+    let Some(files) = &config.tls else {
+      return Ok(Listener::Tcp(listener));
+    };
+    // Several crypto providers may be compiled in, rustls needs to be told which one to use
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    let rustls = RustlsConfig::from_pem_file(&files.cert, &files.key)
+      .await
+      .with_context(|| {
+        format!(
+          "Failed to load the certificate '{}' and key '{}'",
+          files.cert, files.key
+        )
+      })?;
+    Ok(Listener::Tls {
+      listener: listener.into_std()?,
+      rustls,
+      files: files.clone(),
+    })
+  }
+
+  /// Where the webserver can be reached, ex: `https://[::1]:4000`
+  pub fn url(&self) -> String {
+    match self {
+      Listener::Tcp(listener) => listener
+        .local_addr()
+        .map(|addr| format!("http://{}", addr))
+        .unwrap_or_default(),
+      Listener::Tls { listener, .. } => listener
+        .local_addr()
+        .map(|addr| format!("https://{}", addr))
+        .unwrap_or_default(),
+      #[cfg(unix)]
+      Listener::Unix { path, .. } => format!("unix:{}", path.display()),
+    }
+  }
+
+  /// Serve the app until the server fails
+  pub async fn serve(self, app: Router) -> Result<()> {
+    match self {
+      Listener::Tcp(listener) => axum::serve(listener, app).await?,
+      Listener::Tls {
+        listener,
+        rustls,
+        files,
+      } => {
+        let reloaded = rustls.clone();
+        tokio::spawn(async move {
+          if let Err(e) = watch_certificate(files, reloaded).await {
+            log::error!("Error while watching the certificate: {}", e);
+          }
+        });
+        axum_server::from_tcp_rustls(listener, rustls)
+          .serve(app.into_make_service())
+          .await?
+      }
+      #[cfg(unix)]
+      Listener::Unix { listener, .. } => axum::serve(listener, app).await?,
+    }
+    Ok(())
+  }
+}
+
+#[cfg(unix)]
+fn bind_unix(path: &Path) -> Result<Listener> {
+  use std::os::unix::fs::FileTypeExt;
+
+  // A socket left over by a previous run is replaced, anything else is kept
+  if let Ok(metadata) = std::fs::symlink_metadata(path) {
+    if !metadata.file_type().is_socket() {
+      anyhow::bail!(
+        "'{}' already exists and is not a socket, it is left as is",
+        path.display()
+      );
+    }
+    std::fs::remove_file(path)
+      .with_context(|| format!("Failed to remove the socket '{}'", path.display()))?;
+  }
+  let listener = tokio::net::UnixListener::bind(path)
+    .with_context(|| format!("Failed to bind the socket '{}'", path.display()))?;
+  Ok(Listener::Unix {
+    listener,
+    path: path.to_path_buf(),
+  })
+}
+
+#[cfg(not(unix))]
+fn bind_unix(path: &Path) -> Result<Listener> {
+  anyhow::bail!(
+    "Unix domain sockets are not supported on this platform: {}",
+    path.display()
+  )
+}
+
+/// Reload the certificate and key when their files change, a broken pair keeps
+/// the current certificate
+async fn watch_certificate(files: TlsConfig, rustls: RustlsConfig) -> Result<()> {
+  let watched: BTreeSet<PathBuf> = [&files.cert, &files.key]
+    .into_iter()
+    .map(PathBuf::from)
+    .collect();
+
+  let (tx, mut rx) = mpsc::unbounded_channel::<notify::Result<Event>>();
+  let mut watcher = notify::recommended_watcher(move |res| {
+    let _ = tx.send(res);
+  })?;
+
+  // Certificates are usually replaced rather than written to, ex: by certbot,
+  // so their folders are watched
+  let folders: BTreeSet<&Path> = watched.iter().filter_map(|file| file.parent()).collect();
+  for folder in folders {
+    watcher.watch(folder, RecursiveMode::NonRecursive)?;
+  }
+
+  while let Some(res) = rx.recv().await {
+    match res {
+      Ok(event) => {
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+          || !event.paths.iter().any(|path| watched.contains(path))
+        {
+          continue;
+        }
+
+        tokio::time::sleep(TLS_RELOAD_DELAY).await;
+        while rx.try_recv().is_ok() {}
+
+        match rustls.reload_from_pem_file(&files.cert, &files.key).await {
+          Ok(()) => log::info!(
+            "{} » Certificate reloaded: {}",
+            "[webserver]".cyan(),
+            files.cert.bold()
+          ),
+          Err(err) => log::warn!(
+            "{} × Failed to reload the certificate, keeping the current one: {}",
+            "[webserver]".cyan(),
+            err
+          ),
+        }
+      }
+      Err(e) => log::error!("certificate watch error: {:?}", e),
+    }
+  }
+
+  Ok(())
+}
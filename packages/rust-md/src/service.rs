@@ -1,10 +1,19 @@
 use crate::{
   app::{Note, NoteData},
   config::UserConfig,
-  parser,
+  dates,
+  matcher::IgnoreMatcher,
+  parser::{self, Link},
+  policy, redact, schema, seo, slug, stats,
+  vault_path::VaultPath,
 };
 use colored::Colorize;
-use std::{collections::HashMap, fs, path::PathBuf, sync::Arc};
+use std::{
+  collections::HashMap,
+  fs,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
 use tokio::sync::Mutex;
 
 /// Recursively find all notes in the directory and its subdirectories
@@ -12,6 +21,49 @@ pub async fn find_all_notes(
   dir: &PathBuf,
   notes: Arc<Mutex<HashMap<String, Note>>>,
   config: &UserConfig,
+) -> Result<(), String> {
+  let matcher = IgnoreMatcher::new(config);
+  scan_folder(dir, notes, config, &matcher, false).await
+}
+
+/// Like `find_all_notes` but only parse the notes that are not indexed yet
+pub async fn find_new_notes(
+  dir: &PathBuf,
+  notes: Arc<Mutex<HashMap<String, Note>>>,
+  config: &UserConfig,
+) -> Result<(), String> {
+  let matcher = IgnoreMatcher::new(config);
+  scan_folder(dir, notes, config, &matcher, true).await
+}
+
+/// Remove the indexed notes matching the ignore patterns, returns their paths
+pub async fn drop_ignored_notes(
+  notes: Arc<Mutex<HashMap<String, Note>>>,
+  config: &UserConfig,
+) -> Vec<String> {
+  let matcher = IgnoreMatcher::new(config);
+  let root_path = Path::new(&config.root_path);
+
+  let mut notes_guard = notes.lock().await;
+  let ignored: Vec<String> = notes_guard
+    .iter()
+    .filter(|(_, note)| matcher.is_ignored(&root_path.join(note.relative_file_path()), false))
+    .map(|(key, _)| key.clone())
+    .collect();
+
+  for key in &ignored {
+    notes_guard.remove(key);
+  }
+
+  ignored
+}
+
+async fn scan_folder(
+  dir: &PathBuf,
+  notes: Arc<Mutex<HashMap<String, Note>>>,
+  config: &UserConfig,
+  matcher: &IgnoreMatcher,
+  skip_indexed: bool,
 ) -> Result<(), String> {
   let entries = fs::read_dir(dir)
     .map_err(|err| format!("Failed to read directory '{}': {}", dir.display(), err))?;
@@ -23,82 +75,150 @@ pub async fn find_all_notes(
 
     // * If the entry is a directory, recursively search it
     if path.is_dir() {
-      // Check if the directory is in the ignored list, if so skip it
-      if let Some(dir_name) = path.file_name() {
-        let dir_name = dir_name.to_string_lossy().to_string();
-        if config.ignore.contains(&dir_name) {
-          println!(
-            "{} {}",
-            "× Ignoring folder:".bright_black(),
-            format!("{}", path.display()).bright_black().bold()
-          );
-          continue; // Skip this folder
-        }
+      // Check if the directory matches the ignore patterns, if so skip it
+      if matcher.is_ignored(&path, true) {
+        log::debug!(
+          "{} {}",
+          "× Ignoring folder:".bright_black(),
+          format!("{}", path.display()).bright_black().bold()
+        );
+        continue; // Skip this folder
       }
 
-      println!(
+      log::debug!(
         "{} {}",
         "» Scanning folder:".bright_black(),
         format!("{}", path.display()).bright_black().bold()
       );
 
       // Recursively search subdirectories
-      let recursive_call = find_all_notes(&path, notes.clone(), config);
+      let recursive_call = scan_folder(&path, notes.clone(), config, matcher, skip_indexed);
       Box::pin(recursive_call).await?;
 
     // *  If the entry is a file, process it
     } else if path.is_file() && path.extension().and_then(|ext| ext.to_str()) == Some("md") {
-      // Check if the file is a Markdown file
-      let content = fs::read_to_string(&path)
-        .map_err(|err| format!("Failed to read file '{}': {}", path.display(), err))?;
-
-      let relative_path = path
-        .strip_prefix(&config.root_path)
-        .map_err(|err| format!("Failed to get relative path: {}", err))?;
-
-      // Get clean path components
-      let path_str = relative_path
-        .with_extension("")
-        .to_string_lossy()
-        .replace('\\', "/");
-      let absolute_path = format!("/{}", path_str);
-      let full_path = path.to_str().unwrap_or("");
-
-      let file_name = relative_path
-        .file_stem()
-        .and_then(|n| n.to_str())
-        .unwrap_or("")
-        .replace(" ", "%20");
-
-      // Parse Markdown content and extract links
-      let (html_output, metadata, note_links) = parser::markdown_to_html(
-        full_path, &file_name, &content, false, config,
-      )
-      .map_err(|err| {
-        format!(
-          "Failed to convert to markdown '{}': {}",
-          path.display(),
-          err
-        )
-      })?;
-
-      // Store the converted note in the HashMap
-      let converted_note = Note {
-        public: metadata.public.unwrap_or(false),
-        name: file_name.clone(),
-        slug: file_name.replace(" ", "%20"),
-        path: absolute_path,
-        data: NoteData {
-          metadata,
-          links: note_links,
-        },
-        content: html_output,
-      };
-
-      let mut notes_guard = notes.lock().await;
-      notes_guard.insert(path_str, converted_note);
+      if matcher.is_ignored(&path, false) {
+        log::debug!(
+          "{} {}",
+          "× Ignoring file:".bright_black(),
+          format!("{}", path.display()).bright_black().bold()
+        );
+        continue;
+      }
+
+      let vault_path = VaultPath::new(&path, Path::new(&config.root_path))
+        .ok_or_else(|| format!("Failed to get relative path: {}", path.display()))?;
+      if skip_indexed && notes.lock().await.contains_key(vault_path.as_str()) {
+        continue;
+      }
+
+      let (vault_path, converted_note) = build_note(&path, config)?;
+      notes.lock().await.insert(vault_path.key(), converted_note);
     }
   }
 
   Ok(())
 }
+
+/// Parse a markdown file of the vault into a note, the indexer and the watcher
+/// both build the notes with it.
+///
+/// `file_path` is the absolute path of the file, links are resolved against it.
+pub fn build_note(file_path: &Path, config: &UserConfig) -> Result<(VaultPath, Note), String> {
+  let vault_path = VaultPath::new(file_path, Path::new(&config.root_path))
+    .ok_or_else(|| format!("Not in the vault: {}", file_path.display()))?;
+
+  let content = fs::read_to_string(file_path)
+    .map_err(|err| format!("Failed to read file '{}': {}", file_path.display(), err))?;
+  let full_path = file_path.to_string_lossy().replace('\\', "/");
+  let file_name = slug::normalize(vault_path.file_stem());
+
+  // Parse Markdown content and extract links
+  let (html_output, metadata, note_links) =
+    parser::markdown_to_html(&full_path, &file_name, &content, false, config).map_err(|err| {
+      format!(
+        "Failed to convert to markdown '{}': {}",
+        file_path.display(),
+        err
+      )
+    })?;
+
+  // The callers that see the private sections differently than the index
+  // get the links and words of the other side
+  let (private_links, words, public_words) = other_side(
+    &full_path,
+    &file_name,
+    &content,
+    &html_output,
+    &note_links,
+    config,
+  )
+  .map_err(|err| {
+    format!(
+      "Failed to convert to markdown '{}': {}",
+      file_path.display(),
+      err
+    )
+  })?;
+
+  let public = policy::is_public(&config.private, &vault_path.file_path(), &metadata);
+  let seo = seo::note_seo(&full_path, &file_name, &content, &metadata, config);
+  let dates = dates::note_dates(file_path, &metadata, &config.dates);
+  let typed = schema::check_metadata(&metadata, config);
+
+  let note = Note {
+    id: String::new(),
+    public,
+    name: file_name,
+    slug: slug::slug_path(vault_path.as_str(), config.slug_style),
+    path: vault_path.url_path(),
+    data: NoteData {
+      metadata,
+      links: note_links,
+      assets: parser::markdown_assets(&full_path, &content, config),
+      seo,
+      dates,
+      typed,
+      private_assets: parser::private_assets(&full_path, &content, config),
+      private_links,
+      words,
+      public_words,
+    },
+    content: html_output,
+  };
+  Ok((vault_path, note))
+}
+
+/// Links written only in the private sections of a note, and the words of its
+/// content with and without them. `html` and `links` are rendered with `config`.
+fn other_side(
+  file_path: &str,
+  file_name: &str,
+  source: &str,
+  html: &str,
+  links: &[Link],
+  config: &UserConfig,
+) -> anyhow::Result<(Vec<Link>, usize, usize)> {
+  let words = stats::count_words(html);
+  if redact::private_sections(source, true) == redact::private_sections(source, false) {
+    return Ok((Vec::new(), words, words));
+  }
+
+  let mut other_config = config.clone();
+  other_config.private.include = !config.private.include;
+  let (other_html, _, other_links) =
+    parser::markdown_to_html(file_path, file_name, source, false, &other_config)?;
+  let other_words = stats::count_words(&other_html);
+
+  let (all, public, words, public_words) = if config.private.include {
+    (links, other_links.as_slice(), words, other_words)
+  } else {
+    (other_links.as_slice(), links, other_words, words)
+  };
+  let private_links = all
+    .iter()
+    .filter(|link| !public.contains(link))
+    .cloned()
+    .collect();
+  Ok((private_links, words, public_words))
+}
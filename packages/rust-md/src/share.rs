@@ -0,0 +1,219 @@
+use crate::{auth, config::UserConfig, ids};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+  collections::BTreeSet,
+  fs,
+  path::{Path, PathBuf},
+};
+
+/// Validity of a share link when none is asked for, 7 days
+pub const DEFAULT_EXPIRY: i64 = 7 * 24 * 60 * 60;
+
+/// Name of the cookie letting the assets of a shared note through
+pub const ASSETS_COOKIE: &str = "rust-md-share";
+
+/// Query of a share link, ex: `?share=1a2b&note=3c4d&expires=1767225600&assets=true&signature=5e6f`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ShareLink {
+  /// Id of the link, to revoke it
+  pub share: String,
+  /// Stable id of the shared note
+  pub note: String,
+  /// Unix timestamp after which the link is refused
+  pub expires: i64,
+  /// Whether the files referenced by the note are shared too
+  #[serde(default)]
+  pub assets: bool,
+  pub signature: String,
+}
+
+/// The parameters of a share link in a query, all of them or none
+#[derive(Deserialize, Debug, Default)]
+pub struct ShareQuery {
+  share: Option<String>,
+  note: Option<String>,
+  expires: Option<i64>,
+  assets: Option<bool>,
+  signature: Option<String>,
+}
+
+impl ShareQuery {
+  /// `None` when the query is not a share link, an error when it is incomplete
+  pub fn link(self) -> Option<Result<ShareLink, String>> {
+    if self.share.is_none() && self.signature.is_none() {
+      return None;
+    }
+    Some(
+      match (self.share, self.note, self.expires, self.signature) {
+        (Some(share), Some(note), Some(expires), Some(signature)) => Ok(ShareLink {
+          share,
+          note,
+          expires,
+          assets: self.assets.unwrap_or(false),
+          signature,
+        }),
+        _ => Err("Incomplete share link".to_string()),
+      },
+    )
+  }
+}
+
+impl ShareLink {
+  /// The link as a query string, without the leading `?`
+  pub fn query(&self) -> String {
+    format!(
+      "share={}&note={}&expires={}&assets={}&signature={}",
+      self.share, self.note, self.expires, self.assets, self.signature
+    )
+  }
+
+  pub fn expires_at(&self) -> DateTime<Utc> {
+    DateTime::from_timestamp(self.expires, 0).unwrap_or_default()
+  }
+}
+
+/// Revoked share links of a vault, kept in the cache folder
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Revoked {
+  shares: BTreeSet<String>,
+}
+
+/// Signs and checks the share links of a vault
+#[derive(Debug)]
+pub struct Shares {
+  vault: String,
+  secret: Vec<u8>,
+  revoked: Revoked,
+  file: Option<PathBuf>,
+}
+
+impl Shares {
+  /// Use `auth.share_secret`, else the secret of the cache folder, created when missing
+  pub fn load(vault: &str, config: &UserConfig) -> Self {
+    let secret = match &config.auth.share_secret {
+      Some(secret) => secret.as_bytes().to_vec(),
+      None => cached_secret(config),
+    };
+    let file = ids::cache_file(config, "shares");
+    let revoked = file
+      .as_ref()
+      .and_then(|file| fs::read_to_string(file).ok())
+      .and_then(|json| serde_json::from_str(&json).ok())
+      .unwrap_or_default();
+
+    Self {
+      vault: vault.to_string(),
+      secret,
+      revoked,
+      file,
+    }
+  }
+
+  /// A new link to the note with the given id, valid `expires_in` seconds
+  pub fn sign(&self, note_id: &str, assets: bool, expires_in: i64) -> ShareLink {
+    let mut link = ShareLink {
+      share: hex(&random_bytes::<8>()),
+      note: note_id.to_string(),
+      expires: Utc::now().timestamp().saturating_add(expires_in),
+      assets,
+      signature: String::new(),
+    };
+    link.signature = self.signature(&link);
+    link
+  }
+
+  /// Whether the link is genuine, still valid and not revoked
+  pub fn check(&self, link: &ShareLink) -> Result<(), String> {
+    let expected = self.signature(link);
+    if !auth::constant_time_eq(expected.as_bytes(), link.signature.as_bytes()) {
+      return Err("Invalid share link".to_string());
+    }
+    if link.expires < Utc::now().timestamp() {
+      return Err(format!("This share link expired on {}", link.expires_at()));
+    }
+    if self.revoked.shares.contains(&link.share) {
+      return Err("This share link was revoked".to_string());
+    }
+    Ok(())
+  }
+
+  /// Refuse the link from now on, false when it was already revoked
+  pub fn revoke(&mut self, share: &str) -> bool {
+    let added = self.revoked.shares.insert(share.to_string());
+    if added {
+      self.save();
+    }
+    added
+  }
+
+  fn save(&self) {
+    let Some(file) = &self.file else {
+      return;
+    };
+    let result = file
+      .parent()
+      .map_or(Ok(()), fs::create_dir_all)
+      .and_then(|_| fs::write(file, serde_json::to_string_pretty(&self.revoked).unwrap()));
+    if let Err(err) = result {
+      log::warn!(
+        "{} {}",
+        format!("× Failed to write the revoked shares {}:", file.display()).red(),
+        err
+      );
+    }
+  }
+
+  /// Signature of every field of the link but the signature itself
+  fn signature(&self, link: &ShareLink) -> String {
+    let message = format!(
+      "{}\n{}\n{}\n{}\n{}",
+      self.vault, link.note, link.share, link.expires, link.assets
+    );
+    hex(&hmac_sha256(&self.secret, message.as_bytes()))
+  }
+}
+
+/// `{cache_dir}/share-secret`, shared by the vaults. Without a cache folder
+/// the secret only lasts until the server stops.
+fn cached_secret(config: &UserConfig) -> Vec<u8> {
+  let Some(cache_dir) = &config.cache_dir else {
+    return random_bytes::<32>().to_vec();
+  };
+  let file = Path::new(cache_dir).join("share-secret");
+  if let Ok(secret) = fs::read_to_string(&file) {
+    if !secret.trim().is_empty() {
+      return secret.trim().as_bytes().to_vec();
+    }
+  }
+
+  let secret = hex(&random_bytes::<32>());
+  let result = fs::create_dir_all(cache_dir).and_then(|_| fs::write(&file, &secret));
+  if let Err(err) = result {
+    log::warn!(
+      "{} {}",
+      format!("× Failed to write the share secret {}:", file.display()).red(),
+      err
+    );
+  }
+  secret.into_bytes()
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+  let mut bytes = [0; N];
+  getrandom::getrandom(&mut bytes).expect("no source of randomness");
+  bytes
+}
+
+fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+  let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+  mac.update(message);
+  mac.finalize().into_bytes().into()
+}
@@ -0,0 +1,425 @@
+use crate::{
+  app::Note,
+  config::{SlugStyle, UserConfig},
+  feed::{self, Feed, FeedFilter, FeedKind},
+  matcher::IgnoreMatcher,
+  pages::{self, note_title},
+  parser::{self, LinkStyle},
+  seo, slug,
+};
+use pulldown_cmark_escape::{escape_href, escape_html};
+use std::{
+  collections::{BTreeMap, BTreeSet, HashMap},
+  fs,
+  path::{Path, PathBuf},
+};
+
+/// Folder of the tag pages in the exported site
+const TAGS_DIR: &str = "tags";
+
+/// What a static export wrote
+#[derive(Debug, Default)]
+pub struct SiteStats {
+  pub notes: usize,
+  pub assets: usize,
+  pub folders: usize,
+  pub tags: usize,
+  pub feeds: usize,
+}
+
+/// Notes and sub folders of a folder, for its index page
+#[derive(Default)]
+struct Folder<'a> {
+  folders: BTreeSet<String>,
+  notes: Vec<&'a Note>,
+}
+
+/// Render the visible notes to a static site in `output`.
+///
+/// Every note becomes a `.html` file at the same place as in the vault,
+/// along with the assets it references, an index page per folder and a page per tag.
+/// When `site.base_url` is set, the pages get OpenGraph tags, and a sitemap and feeds
+/// of the public notes are written for the site, each tag and each folder.
+pub fn export_site(
+  config: &UserConfig,
+  notes: &HashMap<String, Note>,
+  output: &Path,
+) -> Result<SiteStats, String> {
+  let root = PathBuf::from(&config.root_path);
+  let canonical_root = root.canonicalize();
+  let matcher = IgnoreMatcher::new(config);
+  let mut stats = SiteStats::default();
+  let tag_slugs = TagSlugs::new(notes, config);
+
+  let mut visible: Vec<&Note> = notes
+    .values()
+    .filter(|note| pages::is_visible(note, config))
+    .collect();
+  visible.sort_by(|a, b| a.path.cmp(&b.path));
+
+  let mut folders: BTreeMap<String, Folder> = BTreeMap::new();
+  let mut tags: BTreeMap<String, Vec<&Note>> = BTreeMap::new();
+  let mut pages: BTreeSet<String> = BTreeSet::new();
+  let mut assets: BTreeSet<PathBuf> = BTreeSet::new();
+
+  for note in &visible {
+    let relative_path = note.relative_file_path();
+    let file_path = root.join(&relative_path);
+    let file_path = file_path.to_string_lossy().replace('\\', "/");
+
+    let source = fs::read_to_string(&file_path)
+      .map_err(|err| format!("Failed to read file '{}': {}", file_path, err))?;
+    let (html, metadata, _) =
+      parser::render_markdown(&file_path, &note.name, &source, config, LinkStyle::Static)
+        .map_err(|err| format!("Failed to convert '{}': {}", file_path, err))?;
+
+    let page_path = html_path(&relative_path);
+    let to_root = to_root(&page_path);
+
+    let mut body = html;
+    if let Some(note_tags) = metadata.tags.as_ref().filter(|tags| !tags.is_empty()) {
+      body.push_str("<ul class=\"tags\">\n");
+      for tag in note_tags {
+        body.push_str(&list_item(
+          &format!("{}{}/{}.html", to_root, TAGS_DIR, tag_slugs.get(tag)),
+          &format!("#{}", tag),
+        ));
+      }
+      body.push_str("</ul>\n");
+    }
+
+    // Search engines and social networks need absolute urls
+    let head = match &config.site.base_url {
+      Some(base_url) => seo::meta_tags(&seo::seo_meta(
+        note,
+        config,
+        format!("{}/{}", base_url, slug::encode_path(&page_path)),
+        |asset| format!("{}/{}", base_url, slug::encode_path(asset)),
+      )),
+      None => String::new(),
+    };
+    write_page(
+      output,
+      &page_path,
+      &page(&note_title(note), &to_root, &head, &body),
+    )?;
+    pages.insert(page_path);
+    stats.notes += 1;
+
+    // Index the note in its folder and the folder in its parents
+    let mut folder = pages::parent_path(&relative_path).to_string();
+    folders.entry(folder.clone()).or_default().notes.push(note);
+    while !folder.is_empty() {
+      let parent = pages::parent_path(&folder).to_string();
+      let name = folder[parent.len()..].trim_start_matches('/').to_string();
+      folders
+        .entry(parent.clone())
+        .or_default()
+        .folders
+        .insert(name);
+      folder = parent;
+    }
+
+    for tag in metadata.tags.unwrap_or_default() {
+      tags.entry(tag).or_default().push(note);
+    }
+
+    for asset in parser::markdown_assets(&file_path, &source, config) {
+      let asset = root.join(asset);
+      // Symlinks are followed, the file itself has to be in the vault
+      let inside = match (asset.canonicalize(), &canonical_root) {
+        (Ok(file), Ok(root)) => file.starts_with(root),
+        _ => false,
+      };
+      if inside && asset.is_file() && !matcher.is_ignored(&asset, false) {
+        assets.insert(asset);
+      }
+    }
+  }
+
+  for asset in &assets {
+    let relative_path = asset.strip_prefix(&root).unwrap_or(asset);
+    let destination = output.join(relative_path);
+    if let Some(parent) = destination.parent() {
+      fs::create_dir_all(parent)
+        .map_err(|err| format!("Failed to create '{}': {}", parent.display(), err))?;
+    }
+    fs::copy(asset, &destination)
+      .map_err(|err| format!("Failed to copy '{}': {}", asset.display(), err))?;
+    stats.assets += 1;
+  }
+
+  for (path, folder) in &folders {
+    let page_path = if path.is_empty() {
+      "index.html".to_string()
+    } else {
+      format!("{}/index.html", path)
+    };
+    // A note named `index` takes the place of the folder index
+    if pages.contains(&page_path) {
+      continue;
+    }
+
+    let mut body = format!(
+      "<h1>{}</h1>\n<ul class=\"folders\">\n",
+      html_text(path_title(path))
+    );
+    for name in &folder.folders {
+      body.push_str(&list_item(
+        &format!("{}/index.html", slug::encode_path(name)),
+        name,
+      ));
+    }
+    body.push_str("</ul>\n<ul class=\"notes\">\n");
+    for note in &folder.notes {
+      let file_name = html_path(&note.relative_file_path());
+      let file_name = file_name.rsplit('/').next().unwrap_or_default();
+      body.push_str(&list_item(&slug::encode_path(file_name), &note_title(note)));
+    }
+    body.push_str("</ul>\n");
+
+    write_page(
+      output,
+      &page_path,
+      &page(path_title(path), &to_root(&page_path), "", &body),
+    )?;
+    stats.folders += 1;
+  }
+
+  let mut body = String::from("<h1>Tags</h1>\n<ul class=\"tags\">\n");
+  for (tag, tag_notes) in &tags {
+    body.push_str(&list_item(
+      &format!("{}.html", tag_slugs.get(tag)),
+      &format!("#{} ({})", tag, tag_notes.len()),
+    ));
+
+    let page_path = format!("{}/{}.html", TAGS_DIR, tag_slugs.get(tag));
+    let to_root = to_root(&page_path);
+    let mut tag_body = format!("<h1>#{}</h1>\n<ul class=\"notes\">\n", html_text(tag));
+    for note in tag_notes {
+      tag_body.push_str(&list_item(
+        &format!(
+          "{}{}",
+          to_root,
+          slug::encode_path(&html_path(&note.relative_file_path()))
+        ),
+        &note_title(note),
+      ));
+    }
+    tag_body.push_str("</ul>\n");
+
+    write_page(
+      output,
+      &page_path,
+      &page(&format!("#{}", tag), &to_root, "", &tag_body),
+    )?;
+    stats.tags += 1;
+  }
+  body.push_str("</ul>\n");
+  let page_path = format!("{}/index.html", TAGS_DIR);
+  write_page(
+    output,
+    &page_path,
+    &page("Tags", &to_root(&page_path), "", &body),
+  )?;
+
+  let Some(base_url) = &config.site.base_url else {
+    log::warn!("No site.base_url in the config, the feeds and the sitemap are not exported");
+    return Ok(stats);
+  };
+  let sitemap = seo::sitemap_entries(notes, config, |note| {
+    format!(
+      "{}/{}",
+      base_url,
+      slug::encode_path(&html_path(&note.relative_file_path()))
+    )
+  });
+  write_page(output, "sitemap.xml", &seo::render_sitemap(&sitemap))?;
+
+  let mut filters = vec![FeedFilter::default()];
+  filters.extend(tags.keys().map(|tag| FeedFilter {
+    tag: Some(tag.clone()),
+    folder: None,
+  }));
+  filters.extend(
+    folders
+      .keys()
+      .filter(|folder| !folder.is_empty())
+      .map(|folder| FeedFilter {
+        tag: None,
+        folder: Some(folder.clone()),
+      }),
+  );
+  for filter in &filters {
+    for kind in [FeedKind::Rss, FeedKind::Atom, FeedKind::Json] {
+      let (feed_path, content) = static_feed(config, notes, kind, filter)?;
+      write_page(output, &feed_path, &content)?;
+      stats.feeds += 1;
+    }
+  }
+
+  Ok(stats)
+}
+
+/// Path of a feed in the exported site,
+/// ex: `feed.xml`, `tags/rust/atom.xml` or `blog/feed.json`
+fn feed_path(kind: FeedKind, filter: &FeedFilter, tag_slugs: &TagSlugs) -> String {
+  match (&filter.tag, &filter.folder) {
+    (Some(tag), _) => format!("{}/{}/{}", TAGS_DIR, tag_slugs.get(tag), kind.file_name()),
+    (None, Some(folder)) if !folder.trim_matches('/').is_empty() => {
+      format!("{}/{}", folder.trim_matches('/'), kind.file_name())
+    }
+    _ => kind.file_name().to_string(),
+  }
+}
+
+/// Render a feed of the exported site, with absolute urls based on `site.base_url`.
+///
+/// Returns the path of the feed in the site and its content.
+pub fn static_feed(
+  config: &UserConfig,
+  notes: &HashMap<String, Note>,
+  kind: FeedKind,
+  filter: &FeedFilter,
+) -> Result<(String, String), String> {
+  let base_url = config
+    .site
+    .base_url
+    .as_ref()
+    .ok_or("The feeds need an absolute url, set site.base_url in the config")?;
+
+  let tag_slugs = TagSlugs::new(notes, config);
+  let path = feed_path(kind, filter, &tag_slugs);
+  let home_path = match (&filter.tag, &filter.folder) {
+    (Some(tag), _) => format!("{}/{}.html", TAGS_DIR, tag_slugs.get(tag)),
+    (None, Some(folder)) if !folder.trim_matches('/').is_empty() => {
+      format!("{}/index.html", slug::encode_path(folder.trim_matches('/')))
+    }
+    _ => "index.html".to_string(),
+  };
+
+  let feed = Feed {
+    title: feed::feed_title(config, filter),
+    home_url: format!("{}/{}", base_url, home_path),
+    feed_url: format!("{}/{}", base_url, slug::encode_path(&path)),
+    entries: feed::feed_entries(
+      notes,
+      config,
+      filter,
+      config.site.feed_content,
+      LinkStyle::Static,
+      |note| {
+        format!(
+          "{}/{}",
+          base_url,
+          slug::encode_path(&html_path(&note.relative_file_path()))
+        )
+      },
+    ),
+  };
+
+  Ok((path, feed::render(kind, &feed)))
+}
+
+/// Wrap the content of a page in a minimal html document, `head` is added as is
+fn page(title: &str, to_root: &str, head: &str, body: &str) -> String {
+  format!(
+    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{}</title>\n{}</head>\n<body>\n<nav><a href=\"{}index.html\">Index</a> · <a href=\"{}{}/index.html\">Tags</a></nav>\n<main>\n{}</main>\n</body>\n</html>\n",
+    html_text(title),
+    head,
+    to_root,
+    to_root,
+    TAGS_DIR,
+    body
+  )
+}
+
+fn list_item(href: &str, text: &str) -> String {
+  let mut item = String::from("<li><a href=\"");
+  escape_href(&mut item, href).unwrap();
+  item.push_str("\">");
+  escape_html(&mut item, text).unwrap();
+  item.push_str("</a></li>\n");
+  item
+}
+
+fn write_page(output: &Path, page_path: &str, content: &str) -> Result<(), String> {
+  let destination = output.join(page_path);
+  if let Some(parent) = destination.parent() {
+    fs::create_dir_all(parent)
+      .map_err(|err| format!("Failed to create '{}': {}", parent.display(), err))?;
+  }
+  fs::write(&destination, content)
+    .map_err(|err| format!("Failed to write '{}': {}", destination.display(), err))
+}
+
+fn html_text(text: &str) -> String {
+  let mut escaped = String::new();
+  escape_html(&mut escaped, text).unwrap();
+  escaped
+}
+
+fn path_title(path: &str) -> &str {
+  if path.is_empty() {
+    "Index"
+  } else {
+    path
+  }
+}
+
+/// `sub/note.md` -> `sub/note.html`
+fn html_path(relative_path: &str) -> String {
+  format!("{}.html", relative_path.trim_end_matches(".md"))
+}
+
+/// Relative path from a page to the root of the site, ex: `../../`
+fn to_root(page_path: &str) -> String {
+  "../".repeat(page_path.matches('/').count())
+}
+
+/// File names of the tag pages of the visible notes.
+///
+/// Tags whose slugs collide, ex: `C++` and `c`, or `Rust` and `rust`, get a numbered
+/// slug in the order of the tags: `c` and `c-2`. `index` is the page listing the tags.
+struct TagSlugs(HashMap<String, String>);
+
+impl TagSlugs {
+  fn new(notes: &HashMap<String, Note>, config: &UserConfig) -> Self {
+    let tags: BTreeSet<&String> = notes
+      .values()
+      .filter(|note| pages::is_visible(note, config))
+      .filter_map(|note| note.data.metadata.tags.as_ref())
+      .flatten()
+      .collect();
+
+    let mut taken: BTreeSet<String> = BTreeSet::from(["index".to_string()]);
+    let mut slugs = HashMap::new();
+    for tag in tags {
+      let base = tag_slug(tag);
+      let mut slug = base.clone();
+      let mut number = 2;
+      while !taken.insert(slug.clone()) {
+        slug = format!("{}-{}", base, number);
+        number += 1;
+      }
+      slugs.insert(tag.clone(), slug);
+    }
+    Self(slugs)
+  }
+
+  /// Slug of a tag, the tags no visible note has keep their plain slug
+  fn get(&self, tag: &str) -> String {
+    self.0.get(tag).cloned().unwrap_or_else(|| tag_slug(tag))
+  }
+}
+
+/// File name of a tag page, nested tags (`a/b`) are flattened, ex: `a-b`.
+/// A tag without letters or digits gets `tag`, numbered by `TagSlugs`.
+fn tag_slug(tag: &str) -> String {
+  let slug = slug::slugify(tag, SlugStyle::Kebab);
+  if slug.chars().all(|c| c.is_alphanumeric() || c == '-') {
+    slug
+  } else {
+    "tag".to_string()
+  }
+}
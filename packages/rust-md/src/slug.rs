@@ -0,0 +1,177 @@
+use crate::{app::Note, config::SlugStyle};
+use colored::Colorize;
+use deunicode::deunicode;
+use percent_encoding::{
+  percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS, NON_ALPHANUMERIC,
+};
+use std::collections::{BTreeMap, HashMap};
+use unicode_normalization::UnicodeNormalization;
+
+/// Characters escaped in a path segment, the non-ASCII ones are always escaped.
+/// See <https://url.spec.whatwg.org/#path-percent-encode-set>
+const SEGMENT: &AsciiSet = &CONTROLS
+  .add(b' ')
+  .add(b'"')
+  .add(b'#')
+  .add(b'%')
+  .add(b'/')
+  .add(b'<')
+  .add(b'>')
+  .add(b'?')
+  .add(b'[')
+  .add(b'\\')
+  .add(b']')
+  .add(b'^')
+  .add(b'`')
+  .add(b'{')
+  .add(b'|')
+  .add(b'}');
+
+/// Unicode NFC form, file names created on macOS are often decomposed (NFD)
+pub fn normalize(text: &str) -> String {
+  text.nfc().collect()
+}
+
+/// Slug of a file or folder name, ex: `My Note` is `My Note`, `my-note` or `my-note`
+/// and `Café 🌐` is `Café 🌐`, `café` or `cafe-globe-with-meridians`.
+///
+/// A name without any letter or digit keeps its preserved form.
+pub fn slugify(name: &str, style: SlugStyle) -> String {
+  let name = normalize(name);
+  let slug = match style {
+    SlugStyle::Preserve => return name,
+    SlugStyle::Kebab => kebab(&name, char::is_alphanumeric),
+    SlugStyle::Ascii => kebab(&deunicode(&name), |c| c.is_ascii_alphanumeric()),
+  };
+  if slug.is_empty() {
+    name
+  } else {
+    slug
+  }
+}
+
+/// Lowercase words separated by `-`
+fn kebab(name: &str, is_word: impl Fn(char) -> bool) -> String {
+  let mut slug = String::with_capacity(name.len());
+  for c in name.chars().flat_map(char::to_lowercase) {
+    if is_word(c) {
+      slug.push(c);
+    } else if !slug.is_empty() && !slug.ends_with('-') {
+      slug.push('-');
+    }
+  }
+  slug.truncate(slug.trim_end_matches('-').len());
+  slug
+}
+
+/// Slug of every segment of a path, `.` and `..` are kept for relative links
+pub fn slug_path(path: &str, style: SlugStyle) -> String {
+  path
+    .split('/')
+    .map(|segment| match segment {
+      "" | "." | ".." => segment.to_string(),
+      _ => slugify(segment, style),
+    })
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+/// Percent-encode every segment of a path, keeping the `/`
+pub fn encode_path(path: &str) -> String {
+  path
+    .split('/')
+    .map(|segment| utf8_percent_encode(segment, SEGMENT).to_string())
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+/// Percent-encode a query parameter, ex: `?tag=a%2Fb`
+pub fn encode_component(text: &str) -> String {
+  utf8_percent_encode(text, NON_ALPHANUMERIC).to_string()
+}
+
+/// Decode a percent-encoded path, ex: `My%20Note` -> `My Note`
+pub fn decode_path(path: &str) -> String {
+  percent_decode_str(path).decode_utf8_lossy().to_string()
+}
+
+/// Url path of a link written in a note, ex: `../My%20Note` -> `../my-note`
+pub fn link_url(dest_url: &str, style: SlugStyle) -> String {
+  encode_path(&slug_path(&decode_path(dest_url), style))
+}
+
+/// Paths are looked up case insensitively and whatever their unicode form
+pub fn lookup_key(path: &str) -> String {
+  normalize(path.trim_matches('/')).to_lowercase()
+}
+
+/// Key of a note from its path in the vault (`sub/note`) or its slug
+pub fn find_note<'a>(notes: &'a HashMap<String, Note>, path: &str) -> Option<&'a String> {
+  if let Some((key, _)) = notes.get_key_value(path) {
+    return Some(key);
+  }
+
+  let wanted = lookup_key(path);
+  let mut found = notes
+    .iter()
+    .filter(|(key, note)| lookup_key(&note.slug) == wanted || lookup_key(key) == wanted)
+    .map(|(key, _)| key)
+    .collect::<Vec<_>>();
+  // Colliding notes are reached in a stable order
+  found.sort();
+  found.first().copied()
+}
+
+/// Folder of the vault from its path or its slug, ex: `my-blog` -> `My Blog`
+pub fn find_folder(notes: &HashMap<String, Note>, path: &str, style: SlugStyle) -> Option<String> {
+  let wanted = lookup_key(path);
+  let mut found = notes
+    .values()
+    .flat_map(|note| {
+      let relative_path = note.relative_file_path();
+      let mut folders = Vec::new();
+      let mut folder = relative_path.as_str();
+      while let Some((parent, _)) = folder.rsplit_once('/') {
+        folders.push(parent.to_string());
+        folder = parent;
+      }
+      folders
+    })
+    .filter(|folder| {
+      lookup_key(folder) == wanted || lookup_key(&slug_path(folder, style)) == wanted
+    })
+    .collect::<Vec<_>>();
+  found.sort();
+  found.into_iter().next()
+}
+
+/// Notes whose urls are the same, grouped by url. Only the first one is reached by its slug.
+pub fn collisions(notes: &HashMap<String, Note>) -> Vec<(String, Vec<String>)> {
+  let mut slugs: BTreeMap<String, Vec<String>> = BTreeMap::new();
+  for note in notes.values() {
+    slugs
+      .entry(lookup_key(&note.slug))
+      .or_default()
+      .push(note.relative_file_path());
+  }
+  slugs
+    .into_iter()
+    .filter(|(_, paths)| paths.len() > 1)
+    .map(|(slug, mut paths)| {
+      paths.sort();
+      (slug, paths)
+    })
+    .collect()
+}
+
+/// Warn about the notes sharing the same url
+pub fn report_collisions(notes: &HashMap<String, Note>) {
+  for (slug, paths) in collisions(notes) {
+    log::warn!(
+      "{} {} {}",
+      "× Notes with the same url:".red(),
+      slug.bold(),
+      format!("({})", paths.join(", ")).bright_black()
+    );
+  }
+}
@@ -0,0 +1,154 @@
+use crate::{
+  app::Note,
+  config::UserConfig,
+  dates,
+  pages::{self, is_visible},
+};
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use std::{
+  collections::{BTreeSet, HashMap},
+  fs,
+  path::Path,
+  time::Instant,
+};
+
+/// When and how fast the vault was last indexed
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct IndexInfo {
+  /// End of the last full or partial index
+  pub indexed_at: Option<DateTime<FixedOffset>>,
+  pub duration_ms: u64,
+  /// Last note updated by the watcher
+  pub updated_at: Option<DateTime<FixedOffset>>,
+}
+
+impl IndexInfo {
+  /// Record an index started at `start`
+  pub fn indexed(&mut self, start: Instant) {
+    self.indexed_at = Some(dates::now());
+    self.duration_ms = start.elapsed().as_millis() as u64;
+  }
+
+  pub fn updated(&mut self) {
+    self.updated_at = Some(dates::now());
+  }
+}
+
+/// Statistics of the visible notes
+#[derive(Serialize, Debug, Default)]
+pub struct VaultStats {
+  pub notes: usize,
+  pub public: usize,
+  /// Private notes are only counted when they are shown
+  pub private: usize,
+  pub tags: usize,
+  pub links: usize,
+  pub broken_links: usize,
+  pub words: usize,
+  pub assets: usize,
+  /// Total size of the assets, in bytes
+  pub assets_size: u64,
+}
+
+/// A recently created or updated note
+#[derive(Serialize, Debug)]
+pub struct RecentNote {
+  pub name: String,
+  pub title: String,
+  pub path: String,
+  pub url: String,
+  pub public: bool,
+  pub created: Option<DateTime<FixedOffset>>,
+  pub updated: Option<DateTime<FixedOffset>>,
+}
+
+pub fn vault_stats(notes: &HashMap<String, Note>, config: &UserConfig) -> VaultStats {
+  let mut stats = VaultStats::default();
+  let mut tags = BTreeSet::new();
+  let mut assets = BTreeSet::new();
+
+  for note in notes.values().filter(|note| is_visible(note, config)) {
+    stats.notes += 1;
+    if note.public {
+      stats.public += 1;
+    } else {
+      stats.private += 1;
+    }
+
+    // The index may hold the private sections the caller does not read
+    let include = config.private.include;
+    tags.extend(note.data.metadata.tags.iter().flatten());
+    assets.extend(note.data.visible_assets(include));
+
+    let links = note.data.visible_links(include);
+    stats.links += links.len();
+    stats.broken_links += links
+      .iter()
+      .filter(|link| link.target_vault.is_none() && !Path::new(&link.target_path).is_file())
+      .count();
+    stats.words += note.data.word_count(include);
+  }
+
+  stats.tags = tags.len();
+  for asset in assets {
+    if let Ok(metadata) = fs::metadata(Path::new(&config.root_path).join(asset)) {
+      stats.assets += 1;
+      stats.assets_size += metadata.len();
+    }
+  }
+
+  stats
+}
+
+/// Words of the rendered html, without its tags
+pub(crate) fn count_words(html: &str) -> usize {
+  let mut text = String::with_capacity(html.len());
+  let mut in_tag = false;
+  for c in html.chars() {
+    match c {
+      '<' => in_tag = true,
+      '>' => {
+        in_tag = false;
+        text.push(' ');
+      }
+      _ if !in_tag => text.push(c),
+      _ => {}
+    }
+  }
+  text.split_whitespace().count()
+}
+
+/// The `limit` visible notes most recently updated or created
+pub fn recent_notes(
+  notes: &HashMap<String, Note>,
+  config: &UserConfig,
+  base_url: &str,
+  limit: usize,
+) -> Vec<RecentNote> {
+  let mut recent: Vec<&Note> = notes
+    .values()
+    .filter(|note| is_visible(note, config))
+    .collect();
+  recent.sort_by(|a, b| {
+    b.data
+      .dates
+      .latest()
+      .cmp(&a.data.dates.latest())
+      .then_with(|| a.path.cmp(&b.path))
+  });
+  recent.truncate(limit);
+
+  recent
+    .into_iter()
+    .map(|note| RecentNote {
+      name: note.name.clone(),
+      title: pages::note_title(note),
+      path: note.path.clone(),
+      url: pages::note_url(base_url, note),
+      public: note.public,
+      created: note.data.dates.created,
+      updated: note.data.dates.updated,
+    })
+    .collect()
+}
@@ -0,0 +1,161 @@
+use crate::config::UserConfig;
+use colored::Colorize;
+use minijinja::{context, Environment, Value};
+use serde::Serialize;
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+/// Page wrapping every other template, gets `title`, `content` and `page`,
+/// `page.meta` holds the OpenGraph fields of the note pages
+pub const LAYOUT: &str = "layout.html";
+/// A note, with its metadata, backlinks, outline and navigation
+pub const NOTE: &str = "note.html";
+/// The tags of the vault, or the notes having one tag
+pub const TAGS: &str = "tags.html";
+/// The notes and sub folders of a folder
+pub const FOLDER: &str = "folder.html";
+
+const DEFAULT_LAYOUT: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{{ title }}</title>
+{% if page.meta %}{% set meta = page.meta %}<meta property="og:type" content="article">
+<meta property="og:title" content="{{ meta.title }}">
+<meta property="og:url" content="{{ meta.url }}">
+{% if meta.site_name %}<meta property="og:site_name" content="{{ meta.site_name }}">
+{% endif %}{% if meta.description %}<meta name="description" content="{{ meta.description }}">
+<meta property="og:description" content="{{ meta.description }}">
+{% endif %}{% if meta.image %}<meta property="og:image" content="{{ meta.image }}">
+{% endif %}<meta name="twitter:card" content="{{ meta.card }}">
+<meta name="twitter:title" content="{{ meta.title }}">
+{% if meta.description %}<meta name="twitter:description" content="{{ meta.description }}">
+{% endif %}{% if meta.image %}<meta name="twitter:image" content="{{ meta.image }}">
+{% endif %}{% endif %}</head>
+<body>
+<nav><a href="{{ page.base_url }}/files">Index</a> · <a href="{{ page.base_url }}/tags">Tags</a></nav>
+<main>
+{{ content }}
+</main>
+</body>
+</html>
+"#;
+
+const DEFAULT_NOTE: &str = r#"{% if navigation.breadcrumbs %}<nav class="breadcrumbs">{% for crumb in navigation.breadcrumbs %}<a href="{{ crumb.url }}">{{ crumb.name }}</a> / {% endfor %}</nav>{% endif %}
+{% if outline %}<ul class="outline">
+{% for heading in outline %}<li class="level-{{ heading.level }}">{{ heading.text }}</li>
+{% endfor %}</ul>{% endif %}
+<article>
+{{ content }}
+</article>
+{% if navigation.previous or navigation.next %}<nav class="siblings">{% if navigation.previous %}<a rel="prev" href="{{ navigation.previous.url }}">← {{ navigation.previous.name }}</a>{% endif %}
+{% if navigation.next %}<a rel="next" href="{{ navigation.next.url }}">{{ navigation.next.name }} →</a>{% endif %}</nav>{% endif %}
+{% if note.data.metadata.tags %}<ul class="tags">
+{% for tag in note.data.metadata.tags %}<li><a href="{{ base_url }}/tags/{{ tag }}">#{{ tag }}</a></li>
+{% endfor %}</ul>{% endif %}
+{% if backlinks %}<h2>Backlinks</h2>
+<ul class="backlinks">
+{% for link in backlinks %}<li><a href="{{ link.url }}">{{ link.name }}</a></li>
+{% endfor %}</ul>{% endif %}
+"#;
+
+const DEFAULT_TAGS: &str = r#"{% if tag %}<h1>#{{ tag }}</h1>
+<ul class="notes">
+{% for note in notes %}<li><a href="{{ note.url }}">{{ note.name }}</a></li>
+{% endfor %}</ul>{% else %}<h1>Tags</h1>
+<ul class="tags">
+{% for tag in tags %}<li><a href="{{ tag.url }}">#{{ tag.name }}</a> ({{ tag.count }})</li>
+{% endfor %}</ul>{% endif %}
+"#;
+
+const DEFAULT_FOLDER: &str = r#"<h1>{{ name }}</h1>
+<ul class="folders">
+{% for folder in folders %}<li><a href="{{ folder.url }}">{{ folder.name }}/</a></li>
+{% endfor %}</ul>
+<ul class="notes">
+{% for note in notes %}<li><a href="{{ note.url }}">{{ note.name }}</a></li>
+{% endfor %}</ul>
+"#;
+
+const DEFAULTS: [(&str, &str); 4] = [
+  (LAYOUT, DEFAULT_LAYOUT),
+  (NOTE, DEFAULT_NOTE),
+  (TAGS, DEFAULT_TAGS),
+  (FOLDER, DEFAULT_FOLDER),
+];
+
+/// Html page templates of a vault, read from its `templates` folder.
+/// The built-in templates are used for the missing or invalid ones.
+pub struct Templates {
+  /// `None` when the html mode is off
+  dir: Option<PathBuf>,
+  env: Environment<'static>,
+}
+
+impl Templates {
+  pub fn load(config: &UserConfig) -> Self {
+    let dir = config
+      .templates
+      .as_ref()
+      .map(|templates| Path::new(&config.root_path).join(templates));
+
+    let mut env = Environment::new();
+    for (name, source) in DEFAULTS {
+      env.add_template(name, source).unwrap();
+    }
+
+    if let Some(dir) = &dir {
+      for (name, default) in DEFAULTS {
+        let path = dir.join(name);
+        let Ok(source) = fs::read_to_string(&path) else {
+          continue;
+        };
+        if let Err(err) = env.add_template_owned(name, source) {
+          log::warn!(
+            "{} {}",
+            format!("× Invalid template {}:", path.display()).red(),
+            format!("{}", err).bold()
+          );
+          env.add_template(name, default).unwrap();
+        }
+      }
+    }
+
+    Self { dir, env }
+  }
+
+  /// Whether notes can be rendered as html pages
+  pub fn enabled(&self) -> bool {
+    self.dir.is_some()
+  }
+
+  pub fn dir(&self) -> Option<&Path> {
+    self.dir.as_deref()
+  }
+
+  /// Render a page template with `page` as context, then the layout around it.
+  ///
+  /// `content` is trusted html, it is not escaped.
+  pub fn render<T: Serialize>(
+    &self,
+    name: &str,
+    title: &str,
+    content: &str,
+    page: &T,
+  ) -> Result<String, minijinja::Error> {
+    let page = Value::from_serialize(page);
+    let body = self.env.get_template(name)?.render(context! {
+      content => Value::from_safe_string(content.to_string()),
+      ..page.clone()
+    })?;
+
+    self.env.get_template(LAYOUT)?.render(context! {
+      title,
+      content => Value::from_safe_string(body),
+      page,
+    })
+  }
+}
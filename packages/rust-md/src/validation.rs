@@ -0,0 +1,641 @@
+use crate::{
+  config::{
+    AuthConfig, FieldSchema, FieldType, TokenConfig, UserConfig, VaultConfig, DEFAULT_VAULT,
+  },
+  schema,
+};
+use chrono::format::{Item, StrftimeItems};
+use globset::GlobBuilder;
+use ignore::gitignore::GitignoreBuilder;
+use serde::de::DeserializeOwned;
+use serde_yaml_ng::{Mapping, Value};
+use std::{
+  fmt,
+  path::{Component, Path, PathBuf},
+};
+
+/// Top level fields of the config file
+pub(crate) const FIELDS: [&str; 13] = [
+  "root_path",
+  "private",
+  "ignore",
+  "sanitize",
+  "site",
+  "dates",
+  "slug_style",
+  "auth",
+  "server",
+  "schemas",
+  "cache_dir",
+  "templates",
+  "vaults",
+];
+
+/// Shorter tokens are too easy to guess
+const MIN_TOKEN_LENGTH: usize = 16;
+
+/// Shorter secrets make the share links easy to forge
+const MIN_SECRET_LENGTH: usize = 32;
+
+/// A problem found in the config file
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+  /// Line in the config file, when the field is written in it
+  pub line: Option<usize>,
+  pub message: String,
+}
+
+/// Every problem found in the config file
+#[derive(Debug)]
+pub struct ConfigErrors {
+  pub file: String,
+  pub errors: Vec<ConfigError>,
+}
+
+impl ConfigErrors {
+  pub fn single(file: &str, line: Option<usize>, message: impl Into<String>) -> Self {
+    Self {
+      file: file.to_string(),
+      errors: vec![ConfigError {
+        line,
+        message: message.into(),
+      }],
+    }
+  }
+}
+
+impl From<ConfigErrors> for String {
+  fn from(errors: ConfigErrors) -> Self {
+    errors.to_string()
+  }
+}
+
+impl fmt::Display for ConfigErrors {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (index, error) in self.errors.iter().enumerate() {
+      if index > 0 {
+        writeln!(f)?;
+      }
+      match error.line {
+        Some(line) => write!(f, "{}:{}: {}", self.file, line, error.message)?,
+        None => write!(f, "{}: {}", self.file, error.message)?,
+      }
+    }
+    Ok(())
+  }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// Segment of a field path, used to find the line of a field
+#[derive(Clone, Copy)]
+enum Key<'a> {
+  Field(&'a str),
+  Index(usize),
+}
+
+/// Collects the errors while validating
+struct Validator<'a> {
+  source: &'a str,
+  errors: Vec<ConfigError>,
+}
+
+impl Validator<'_> {
+  fn error(&mut self, path: &[Key], message: impl Into<String>) {
+    self.errors.push(ConfigError {
+      line: field_line(self.source, path),
+      message: message.into(),
+    });
+  }
+
+  /// Deserialize a top level field, `None` when missing or invalid
+  fn field<T: DeserializeOwned>(&mut self, fields: &Mapping, name: &str) -> Option<T> {
+    let value = fields.get(name)?.clone();
+
+    match serde_path_to_error::deserialize::<_, T>(value.clone()) {
+      Ok(value) => Some(value),
+      Err(err) => {
+        // Check each sub field on its own to report all of their problems
+        let mut reported = false;
+        if let Value::Mapping(sub_fields) = &value {
+          if sub_fields.len() > 1 {
+            for (key, sub_value) in sub_fields {
+              let single = Mapping::from_iter([(key.clone(), sub_value.clone())]);
+              if let Err(err) = serde_path_to_error::deserialize::<_, T>(Value::Mapping(single)) {
+                self.path_error(name, &err);
+                reported = true;
+              }
+            }
+          }
+        }
+        if !reported {
+          self.path_error(name, &err);
+        }
+        None
+      }
+    }
+  }
+
+  fn path_error(&mut self, name: &str, err: &serde_path_to_error::Error<serde_yaml_ng::Error>) {
+    let mut path = vec![Key::Field(name)];
+    let mut field_path = name.to_string();
+    for segment in err.path().iter() {
+      match segment {
+        serde_path_to_error::Segment::Seq { index } => {
+          path.push(Key::Index(*index));
+          field_path.push_str(&format!("[{}]", index));
+        }
+        serde_path_to_error::Segment::Map { key } => {
+          path.push(Key::Field(key));
+          field_path.push_str(&format!(".{}", key));
+        }
+        _ => {}
+      }
+    }
+    self.error(&path, format!("{}: {}", field_path, err.inner()));
+  }
+
+  /// Deserialize an optional top level field, using its default when missing
+  fn optional<T: DeserializeOwned + Default>(&mut self, fields: &Mapping, name: &str) -> T {
+    self.field(fields, name).unwrap_or_default()
+  }
+
+  /// Tokens and the share secret must be long enough, the token names unique
+  fn check_auth(&mut self, auth: &AuthConfig) {
+    let mut names: Vec<&str> = Vec::new();
+    let file_tokens = auth
+      .file_tokens
+      .iter()
+      .enumerate()
+      .map(|(index, token)| (format!("auth.token_file[{}]", index), Vec::new(), token));
+    let tokens = auth.tokens.iter().enumerate().map(|(index, token)| {
+      (
+        format!("auth.tokens[{}]", index),
+        vec![Key::Field("auth"), Key::Field("tokens"), Key::Index(index)],
+        token,
+      )
+    });
+
+    for (field, path, token) in tokens.chain(file_tokens) {
+      if token.name.is_empty() || names.contains(&token.name.as_str()) {
+        self.error(
+          &path,
+          format!(
+            "{}.name: '{}' must be unique and not empty",
+            field, token.name
+          ),
+        );
+      }
+      names.push(&token.name);
+
+      if token.token.chars().count() < MIN_TOKEN_LENGTH {
+        self.error(
+          &path,
+          format!(
+            "{}.token: must be at least {} characters long",
+            field, MIN_TOKEN_LENGTH
+          ),
+        );
+      }
+      if token.scopes.is_empty() {
+        self.error(&path, format!("{}.scopes: must not be empty", field));
+      }
+    }
+
+    if let Some(secret) = &auth.share_secret {
+      if secret.chars().count() < MIN_SECRET_LENGTH {
+        self.error(
+          &[Key::Field("auth"), Key::Field("share_secret")],
+          format!(
+            "auth.share_secret: must be at least {} characters long",
+            MIN_SECRET_LENGTH
+          ),
+        );
+      }
+    }
+  }
+
+  /// Check the ignore patterns and visibility rules of a vault,
+  /// `vault_path` being empty for the top level one
+  fn check_vault(&mut self, vault: &VaultConfig, vault_path: &[Key]) {
+    let prefix = match vault_path {
+      [Key::Field(name), Key::Index(index)] => format!("{}[{}].", name, index),
+      _ => String::new(),
+    };
+    let path =
+      |keys: &[Key<'static>]| -> Vec<Key> { vault_path.iter().chain(keys).copied().collect() };
+
+    let mut ignore_builder = GitignoreBuilder::new(&vault.root_path);
+    for (index, pattern) in vault.ignore.iter().enumerate() {
+      if let Err(err) = ignore_builder.add_line(None, pattern) {
+        self.error(
+          &path(&[Key::Field("ignore"), Key::Index(index)]),
+          format!(
+            "{}ignore[{}]: invalid pattern '{}': {}",
+            prefix, index, pattern, err
+          ),
+        );
+      }
+    }
+
+    if let Some(templates) = &vault.templates {
+      let resolved = Path::new(&vault.root_path).join(templates);
+      if !vault.root_path.is_empty() && !resolved.is_dir() {
+        self.error(
+          &path(&[Key::Field("templates")]),
+          format!(
+            "{}templates: '{}' is not a folder of the vault (resolved to '{}')",
+            prefix,
+            templates,
+            resolved.display()
+          ),
+        );
+      }
+    }
+
+    for (index, rule) in vault.private.rules.iter().enumerate() {
+      if let Some(pattern) = &rule.path {
+        if let Err(err) = GlobBuilder::new(pattern).literal_separator(true).build() {
+          self.error(
+            &path(&[
+              Key::Field("private"),
+              Key::Field("rules"),
+              Key::Index(index),
+              Key::Field("path"),
+            ]),
+            format!("{}private.rules[{}].path: {}", prefix, index, err),
+          );
+        }
+      }
+    }
+  }
+}
+
+/// Validate the whole config and report every problem at once.
+///
+/// `path` is the config file, relative paths are resolved against its folder.
+pub fn validate(path: &str, source: &str, value: Value) -> Result<UserConfig, ConfigErrors> {
+  let mut validator = Validator {
+    source,
+    errors: Vec::new(),
+  };
+
+  let fields = match value {
+    Value::Mapping(fields) => fields,
+    Value::Null => Mapping::new(),
+    _ => {
+      return Err(ConfigErrors::single(
+        path,
+        None,
+        "expected a list of fields",
+      ))
+    }
+  };
+
+  for key in fields.keys() {
+    let name = key.as_str().unwrap_or_default();
+    if !FIELDS.contains(&name) {
+      validator.error(
+        &[Key::Field(name)],
+        format!(
+          "unknown field `{}`, expected one of `{}`",
+          name,
+          FIELDS.join("`, `")
+        ),
+      );
+    }
+  }
+
+  let root_path: Option<String> = validator.field(&fields, "root_path");
+  if !fields.contains_key("root_path") {
+    validator.error(&[], "missing field `root_path`");
+  }
+
+  let config_dir = Path::new(path).parent().unwrap_or(Path::new(""));
+  let root_path = root_path.map(|root_path| {
+    let resolved = resolve_path(&root_path, config_dir);
+    if !resolved.is_dir() {
+      validator.error(
+        &[Key::Field("root_path")],
+        format!(
+          "root path '{}' is not a directory (resolved to '{}')",
+          root_path,
+          resolved.display()
+        ),
+      );
+    }
+    resolved.to_string_lossy().to_string()
+  });
+
+  let mut config = UserConfig {
+    root_path: root_path.unwrap_or_default(),
+    private: validator.optional(&fields, "private"),
+    ignore: validator.optional(&fields, "ignore"),
+    sanitize: validator.optional(&fields, "sanitize"),
+    site: validator.optional(&fields, "site"),
+    dates: validator.optional(&fields, "dates"),
+    slug_style: validator.optional(&fields, "slug_style"),
+    auth: validator.optional(&fields, "auth"),
+    server: validator.optional(&fields, "server"),
+    schemas: validator.optional(&fields, "schemas"),
+    cache_dir: validator.field(&fields, "cache_dir"),
+    templates: validator.field(&fields, "templates"),
+    vaults: validator.optional(&fields, "vaults"),
+  };
+
+  let cache_dir = config.cache_dir.as_deref().unwrap_or(".rust-md");
+  config.cache_dir = Some(
+    resolve_path(cache_dir, config_dir)
+      .to_string_lossy()
+      .to_string(),
+  );
+
+  if let Some(base_url) = config.site.base_url.as_mut() {
+    if !base_url.starts_with("http://") && !base_url.starts_with("https://") {
+      validator.error(
+        &[Key::Field("site"), Key::Field("base_url")],
+        format!(
+          "site.base_url: '{}' must be an absolute http(s) url",
+          base_url
+        ),
+      );
+    }
+    *base_url = base_url.trim_end_matches('/').to_string();
+  }
+
+  if let Some(token_file) = config.auth.token_file.as_mut() {
+    let resolved = resolve_path(token_file, config_dir);
+    let tokens = std::fs::read_to_string(&resolved)
+      .map_err(|err| err.to_string())
+      .and_then(|source| {
+        serde_yaml_ng::from_str::<Vec<TokenConfig>>(&source).map_err(|err| err.to_string())
+      });
+    match tokens {
+      Ok(tokens) => config.auth.file_tokens = tokens,
+      Err(err) => validator.error(
+        &[Key::Field("auth"), Key::Field("token_file")],
+        format!(
+          "auth.token_file: failed to read '{}': {}",
+          resolved.display(),
+          err
+        ),
+      ),
+    }
+    *token_file = resolved.to_string_lossy().to_string();
+  }
+  validator.check_auth(&config.auth);
+
+  if let Some(socket) = config.server.unix_socket.as_mut() {
+    if !cfg!(unix) {
+      validator.error(
+        &[Key::Field("server"), Key::Field("unix_socket")],
+        "server.unix_socket: unix domain sockets are not supported on this platform",
+      );
+    }
+    if config.server.tls.is_some() {
+      validator.error(
+        &[Key::Field("server"), Key::Field("tls")],
+        "server.tls: https is not served on a unix socket, the reverse proxy handles it",
+      );
+    }
+    *socket = resolve_path(socket, config_dir)
+      .to_string_lossy()
+      .to_string();
+  }
+  if let Some(tls) = config.server.tls.as_mut() {
+    for (field, file) in [("cert", &mut tls.cert), ("key", &mut tls.key)] {
+      let resolved = resolve_path(file, config_dir);
+      if !resolved.is_file() {
+        validator.error(
+          &[Key::Field("server"), Key::Field("tls"), Key::Field(field)],
+          format!(
+            "server.tls.{}: '{}' is not a file (resolved to '{}')",
+            field,
+            file,
+            resolved.display()
+          ),
+        );
+      }
+      *file = resolved.to_string_lossy().to_string();
+    }
+  }
+
+  for (index, format) in config.dates.formats.iter().enumerate() {
+    let valid = StrftimeItems::new(format).all(|item| item != Item::Error);
+    if !valid {
+      validator.error(
+        &[
+          Key::Field("dates"),
+          Key::Field("formats"),
+          Key::Index(index),
+        ],
+        format!("dates.formats[{}]: invalid date format '{}'", index, format),
+      );
+    }
+  }
+
+  // Defaults and allowed values get the type of their field
+  let formats = config.dates.formats.clone();
+  for (r#type, schema) in config.schemas.iter_mut() {
+    for (name, field) in schema.fields.iter_mut() {
+      let path = [
+        Key::Field("schemas"),
+        Key::Field(r#type),
+        Key::Field("fields"),
+        Key::Field(name),
+      ];
+      let label = format!("schemas.{}.fields.{}", r#type, name);
+
+      if let Some(allowed) = field.r#enum.as_mut() {
+        let item_schema = FieldSchema {
+          r#type: match field.r#type {
+            FieldType::List => FieldType::String,
+            r#type => r#type,
+          },
+          required: false,
+          r#enum: None,
+          default: None,
+        };
+        for value in allowed.iter_mut() {
+          match schema::coerce(value, &item_schema, &formats) {
+            Ok(coerced) => *value = coerced,
+            Err(message) => validator.error(
+              &[path[0], path[1], path[2], path[3], Key::Field("enum")],
+              format!("{}.enum: {}", label, message),
+            ),
+          }
+        }
+      }
+      if let Some(default) = field.default.as_ref() {
+        match schema::coerce(default, field, &formats) {
+          Ok(coerced) => field.default = Some(coerced),
+          Err(message) => validator.error(
+            &[path[0], path[1], path[2], path[3], Key::Field("default")],
+            format!("{}.default: {}", label, message),
+          ),
+        }
+      }
+    }
+  }
+
+  validator.check_vault(&config.vault(DEFAULT_VAULT).unwrap(), &[]);
+
+  let mut names = vec![DEFAULT_VAULT.to_string()];
+  for (index, vault) in config.vaults.iter_mut().enumerate() {
+    let path = [Key::Field("vaults"), Key::Index(index)];
+    let field = format!("vaults[{}]", index);
+
+    let name_path = [path[0], path[1], Key::Field("name")];
+    if names.contains(&vault.name) {
+      validator.error(
+        &name_path,
+        format!(
+          "{}.name: the vault name '{}' is already used",
+          field, vault.name
+        ),
+      );
+    } else if vault.name.is_empty()
+      || !vault
+        .name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+      validator.error(
+        &name_path,
+        format!(
+          "{}.name: '{}' must only contain letters, digits, `-` and `_`",
+          field, vault.name
+        ),
+      );
+    }
+    names.push(vault.name.clone());
+
+    let resolved = resolve_path(&vault.root_path, config_dir);
+    if !resolved.is_dir() {
+      validator.error(
+        &[path[0], path[1], Key::Field("root_path")],
+        format!(
+          "{}.root_path: root path '{}' is not a directory (resolved to '{}')",
+          field,
+          vault.root_path,
+          resolved.display()
+        ),
+      );
+    }
+    vault.root_path = resolved.to_string_lossy().to_string();
+
+    validator.check_vault(vault, &path);
+  }
+
+  if validator.errors.is_empty() {
+    Ok(config)
+  } else {
+    validator.errors.sort_by_key(|error| error.line);
+    Err(ConfigErrors {
+      file: path.to_string(),
+      errors: validator.errors,
+    })
+  }
+}
+
+/// Expand `~` and resolve a relative path against `base_dir`
+pub fn resolve_path(path: &str, base_dir: &Path) -> PathBuf {
+  let path = match path.strip_prefix('~') {
+    Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+      match std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE")) {
+        Ok(home) => PathBuf::from(home).join(rest.trim_start_matches(['/', '\\'])),
+        Err(_) => PathBuf::from(path),
+      }
+    }
+    _ => PathBuf::from(path),
+  };
+
+  let path = if path.is_absolute() {
+    path
+  } else {
+    let base_dir = if base_dir.is_absolute() {
+      base_dir.to_path_buf()
+    } else {
+      std::env::current_dir().unwrap_or_default().join(base_dir)
+    };
+    base_dir.join(path)
+  };
+
+  // Remove `.` and `..` without touching the file system
+  let mut normalized = PathBuf::new();
+  for component in path.components() {
+    match component {
+      Component::CurDir => {}
+      Component::ParentDir => {
+        normalized.pop();
+      }
+      _ => normalized.push(component),
+    }
+  }
+  normalized
+}
+
+/// Line (1-based) of a field in the yaml source, `None` when it is not written in it
+fn field_line(source: &str, path: &[Key]) -> Option<usize> {
+  let lines: Vec<&str> = source.lines().collect();
+  let mut start = 0;
+  let mut parent_indent: Option<usize> = None;
+  // Whether the first line to search is a list item holding the field
+  let mut in_item = false;
+  let mut found = None;
+
+  for key in path {
+    let mut hit = None;
+    let mut items = 0;
+    let mut item_indent = None;
+
+    for (index, line) in lines.iter().enumerate().skip(start) {
+      let content = line.trim_start();
+      if content.is_empty() || content.starts_with('#') {
+        continue;
+      }
+      let indent = line.len() - content.len();
+      let is_item = content.starts_with("- ") || content == "-";
+
+      // Left the block of the parent field
+      if let Some(parent) = parent_indent {
+        let list_item = is_item && matches!(key, Key::Index(_));
+        let item_line = in_item && index == start;
+        if !item_line && (indent < parent || (indent == parent && !list_item)) {
+          break;
+        }
+      }
+
+      match key {
+        Key::Field(name) => {
+          let text = content.strip_prefix("- ").unwrap_or(content);
+          if text
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.trim_start().starts_with(':'))
+          {
+            hit = Some((index, indent));
+            break;
+          }
+        }
+        Key::Index(item) => {
+          if is_item && *item_indent.get_or_insert(indent) == indent {
+            if items == *item {
+              hit = Some((index, indent));
+              break;
+            }
+            items += 1;
+          }
+        }
+      }
+    }
+
+    let (index, indent) = hit?;
+    found = Some(index + 1);
+    parent_indent = Some(indent);
+    // The first field of a list item is on the same line
+    in_item = matches!(key, Key::Index(_));
+    start = if in_item { index } else { index + 1 };
+  }
+
+  found
+}
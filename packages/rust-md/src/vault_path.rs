@@ -0,0 +1,69 @@
+use std::{
+  fmt,
+  path::{Path, PathBuf},
+};
+
+/// Path of a note relative to the root of its vault, without the `.md` extension
+/// and with `/` separators, ex: `sub/note`. The notes are indexed by it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VaultPath(String);
+
+impl VaultPath {
+  /// `None` when the file is not in the vault
+  pub fn new(file_path: &Path, root_path: &Path) -> Option<Self> {
+    let relative_path = file_path.strip_prefix(root_path).ok()?;
+    let path = relative_path
+      .with_extension("")
+      .to_string_lossy()
+      .replace('\\', "/");
+    (!path.is_empty()).then_some(Self(path))
+  }
+
+  /// Path of an indexed note from its key
+  pub fn from_key(key: &str) -> Self {
+    Self(key.to_string())
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+
+  /// Key of the note in the index
+  pub fn key(&self) -> String {
+    self.0.clone()
+  }
+
+  /// `sub/note` -> `note`
+  pub fn file_stem(&self) -> &str {
+    self.0.rsplit('/').next().unwrap_or_default()
+  }
+
+  /// `sub/note` -> `sub/note.md`
+  pub fn file_path(&self) -> String {
+    format!("{}.md", self.0)
+  }
+
+  /// `sub/note` -> `/sub/note`, the `path` of the note
+  pub fn url_path(&self) -> String {
+    format!("/{}", self.0)
+  }
+
+  /// Whether the note is in `folder` or one of its sub folders
+  pub fn is_in(&self, folder: &VaultPath) -> bool {
+    self
+      .0
+      .strip_prefix(&folder.0)
+      .is_some_and(|rest| rest.starts_with('/'))
+  }
+
+  /// Path of the markdown file
+  pub fn absolute(&self, root_path: &str) -> PathBuf {
+    Path::new(root_path).join(self.file_path())
+  }
+}
+
+impl fmt::Display for VaultPath {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.0)
+  }
+}
@@ -1,107 +1,277 @@
 use crate::{
-  app::{Note, NoteData},
   config::UserConfig,
-  parser,
+  matcher::{IgnoreMatcher, IGNORE_FILE},
+  reload, service,
+  templates::Templates,
+  vault_path::VaultPath,
+  Vault,
 };
 use anyhow::Result;
 use colored::Colorize;
 use notify::{Event, RecursiveMode, Watcher};
+use serde::Serialize;
 use std::{
-  collections::HashMap,
-  fs,
-  path::Path,
-  sync::{mpsc, Arc},
+  collections::HashSet,
+  path::{Path, PathBuf},
 };
-use tokio::sync::Mutex;
+use tokio::sync::mpsc;
 
-pub async fn watch_files(
-  root_path: &str,
-  notes: Arc<Mutex<HashMap<String, Note>>>,
-  config: &UserConfig,
-) -> Result<()> {
-  let (tx, rx) = mpsc::channel::<notify::Result<Event>>();
+/// Change of the index, sent to the subscribers of the vault
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IndexEvent {
+  /// A note was added or parsed again, `path` is its key, ex: `sub/note`
+  Updated {
+    path: String,
+  },
+  Removed {
+    path: String,
+  },
+  /// The whole vault was indexed again, ex: after a config change
+  Reindexed,
+}
+
+/// Watch the vault and update its index, templates and ignored files as they change
+pub async fn watch_files(root_path: &str, state: Vault) -> Result<()> {
+  let notes = state.notes.clone();
+  let config = state.config.clone();
+  let templates = state.templates.clone();
+
+  let (tx, mut rx) = mpsc::unbounded_channel::<notify::Result<Event>>();
 
   // Use recommended_watcher() to automatically select the best implementation
-  // for your platform. The `EventHandler` passed to this constructor can be a
-  // closure, a `std::sync::mpsc::Sender`, a `crossbeam_channel::Sender`, or
-  // another type the trait is implemented for.
-  let mut watcher = notify::recommended_watcher(tx)?;
+  // for your platform. The events are forwarded to a tokio channel so the
+  // runtime is not blocked while waiting for them.
+  let mut watcher = notify::recommended_watcher(move |res| {
+    let _ = tx.send(res);
+  })?;
 
   // Add a path to be watched. All files and directories at that path and
   // below will be monitored for changes.
   watcher.watch(Path::new(root_path), RecursiveMode::Recursive)?;
-  // Block forever, printing out events as they come in
-  for res in rx {
+
+  // Wait forever, printing out events as they come in
+  while let Some(res) = rx.recv().await {
     match res {
       Ok(event) => {
-        // println!("event: {:?}", event);
-        if let notify::Event {
-          kind: notify::EventKind::Modify(notify::event::ModifyKind::Any),
-          paths,
-          ..
-        } = &event
+        // The config can be reloaded at any time
+        let config = config.read().await.clone();
+        let config = &config;
+
+        let changed = matches!(
+          event.kind,
+          notify::EventKind::Create(_)
+            | notify::EventKind::Modify(_)
+            | notify::EventKind::Remove(_)
+        );
+
+        // Apply the new ignore file right away
+        if changed
+          && event
+            .paths
+            .iter()
+            .any(|path| path.file_name().is_some_and(|name| name == IGNORE_FILE))
         {
-          for path in paths {
-            // Check if the path contain a directory that is in the ignored list
-            if check_ignore_patterns(path, config) {
-              continue; // Skip
-            }
+          log::info!(
+            "{} » {} changed, updating ignored files",
+            "[watcher]".purple(),
+            IGNORE_FILE.bold()
+          );
+          reload::reindex(&state, config, false).await;
+          continue;
+        }
+
+        // Read the templates again so the pages use them right away
+        let templates_dir = templates.read().await.dir().map(Path::to_path_buf);
+        if let Some(templates_dir) = templates_dir
+          .filter(|dir| changed && event.paths.iter().any(|path| path.starts_with(dir)))
+        {
+          log::info!(
+            "{} » Templates changed, reloading {}",
+            "[watcher]".purple(),
+            format!("{}", templates_dir.display()).bold()
+          );
+          *templates.write().await = Templates::load(config);
+          continue;
+        }
+
+        if !changed {
+          continue;
+        }
 
-            if path.extension().and_then(|ext| ext.to_str()) == Some("md") {
-              println!(
-                "{} » Updating: {}",
-                "[watcher]".purple(),
-                format!("{}", path.display()).bold()
-              );
-
-              let content = fs::read_to_string(path)?;
-              let relative_path = path.strip_prefix(root_path)?;
-              let file_path = relative_path.to_string_lossy().replace('\\', "/");
-              let file_name = relative_path
-                .file_stem()
-                .and_then(|n| n.to_str())
-                .unwrap()
-                .replace(" ", "%20");
-
-              let (html_output, metadata, links) =
-                parser::markdown_to_html(&file_path, &file_name, &content, false, config)?;
-
-              let converted_note = Note {
-                public: metadata.public.unwrap_or(false),
-                name: file_name.clone(),
-                slug: file_name.replace(" ", "%20"),
-                path: file_path.clone(),
-                data: NoteData { metadata, links },
-                content: html_output,
-              };
-
-              let mut notes_guard = notes.lock().await;
-              notes_guard.insert(file_path, converted_note);
+        // Every path of the event is indexed again, a rename has the old and the new one
+        let matcher = IgnoreMatcher::new(config);
+        let mut updated = Vec::new();
+        for path in &event.paths {
+          updated.extend(update_path(path, &state, config, &matcher).await);
+        }
+        if updated.is_empty() {
+          continue;
+        }
+        let backlinks = update_backlinks(&updated, &state, config).await;
+
+        let mut notes_guard = notes.lock().await;
+        {
+          let mut ids = state.ids.lock().await;
+          ids.assign(&mut notes_guard);
+          ids.save();
+        }
+        state.index.write().await.updated();
+
+        let root_path = Path::new(&config.root_path);
+        for path in updated.iter().chain(&backlinks) {
+          let Some(vault_path) = VaultPath::new(path, root_path) else {
+            continue;
+          };
+          let event = if notes_guard.contains_key(vault_path.as_str()) {
+            IndexEvent::Updated {
+              path: vault_path.key(),
+            }
+          } else {
+            IndexEvent::Removed {
+              path: vault_path.key(),
             }
-          }
+          };
+          // Nobody may be listening
+          let _ = state.events.send(event);
         }
       }
-      Err(e) => println!("watch error: {:?}", e),
+      Err(e) => log::error!("watch error: {:?}", e),
     }
   }
 
   Ok(())
 }
 
-fn check_ignore_patterns(path: &Path, config: &UserConfig) -> bool {
-  if let Some(dir_name) = path.parent() {
-    // println!("dir_name: {}", dir_name.display());
-    let dir_name = dir_name.to_string_lossy().to_string();
-    for ignore_pattern in &config.ignore {
-      if dir_name.contains(ignore_pattern) {
-        println!(
-          "{} × Ignoring: {}",
-          "[watcher]".purple(),
-          format!("{}", path.display()).bright_black().bold()
-        );
-        return true; // Skip this folder
+/// Index, parse again or remove the notes at `path`, a markdown file or a folder.
+/// Returns the markdown files whose note changed.
+async fn update_path(
+  path: &Path,
+  state: &Vault,
+  config: &UserConfig,
+  matcher: &IgnoreMatcher,
+) -> Vec<PathBuf> {
+  let root_path = Path::new(&config.root_path);
+  let is_note = path.extension().and_then(|ext| ext.to_str()) == Some("md");
+
+  // Check if the path matches the ignore patterns
+  if path.exists() && matcher.is_ignored(path, path.is_dir()) {
+    log::info!(
+      "{} × Ignoring: {}",
+      "[watcher]".purple(),
+      format!("{}", path.display()).bright_black().bold()
+    );
+    return Vec::new();
+  }
+
+  // A folder created or moved into the vault
+  if path.is_dir() {
+    let Some(folder) = VaultPath::new(path, root_path) else {
+      return Vec::new();
+    };
+    if let Err(err) =
+      service::find_new_notes(&path.to_path_buf(), state.notes.clone(), config).await
+    {
+      log::warn!("{} {}", "[watcher]".purple(), format!("× {}", err).red());
+    }
+    return state
+      .notes
+      .lock()
+      .await
+      .keys()
+      .map(|key| VaultPath::from_key(key))
+      .filter(|vault_path| vault_path.is_in(&folder))
+      .map(|vault_path| vault_path.absolute(&config.root_path))
+      .collect();
+  }
+
+  if path.is_file() {
+    if !is_note {
+      return Vec::new();
+    }
+    log::info!(
+      "{} » Updating: {}",
+      "[watcher]".purple(),
+      format!("{}", path.display()).bold()
+    );
+    return match service::build_note(path, config) {
+      Ok((vault_path, note)) => {
+        state.notes.lock().await.insert(vault_path.key(), note);
+        vec![path.to_path_buf()]
+      }
+      Err(err) => {
+        log::warn!("{} {}", "[watcher]".purple(), format!("× {}", err).red());
+        Vec::new()
+      }
+    };
+  }
+
+  // A note or a folder removed or moved out of the vault
+  let Some(removed) = VaultPath::new(path, root_path) else {
+    return Vec::new();
+  };
+  let mut notes_guard = state.notes.lock().await;
+  let keys: Vec<String> = notes_guard
+    .keys()
+    .filter(|key| {
+      let vault_path = VaultPath::from_key(key);
+      if is_note {
+        vault_path == removed
+      } else {
+        vault_path.is_in(&removed)
+      }
+    })
+    .cloned()
+    .collect();
+
+  let mut updated = Vec::new();
+  for key in keys {
+    if let Some(note) = notes_guard.remove(&key) {
+      log::info!(
+        "{} » Removing: {}",
+        "[watcher]".purple(),
+        note.relative_file_path().bold()
+      );
+      updated.push(root_path.join(note.relative_file_path()));
+    }
+  }
+  updated
+}
+
+/// Parse again the notes linking to the updated files, so their links are up to date.
+/// Returns the markdown files parsed again.
+async fn update_backlinks(updated: &[PathBuf], state: &Vault, config: &UserConfig) -> Vec<PathBuf> {
+  let root_path = Path::new(&config.root_path);
+  let targets: HashSet<String> = updated
+    .iter()
+    .map(|path| path.to_string_lossy().replace('\\', "/"))
+    .collect();
+
+  let sources: Vec<PathBuf> = state
+    .notes
+    .lock()
+    .await
+    .values()
+    .filter(|note| {
+      note
+        .data
+        .links
+        .iter()
+        .any(|link| link.target_vault.is_none() && targets.contains(&link.target_path))
+    })
+    .map(|note| root_path.join(note.relative_file_path()))
+    .filter(|path| !updated.contains(path))
+    .collect();
+
+  let mut parsed = Vec::new();
+  for source in sources {
+    match service::build_note(&source, config) {
+      Ok((vault_path, note)) => {
+        state.notes.lock().await.insert(vault_path.key(), note);
+        parsed.push(source);
       }
+      Err(err) => log::warn!("{} {}", "[watcher]".purple(), format!("× {}", err).red()),
     }
   }
-  false // Do not skip this folder
+  parsed
 }
@@ -0,0 +1,54 @@
+//! Callers without the `read-private` scope see the notes without their private
+//! sections, even when the index keeps them.
+
+mod common;
+
+use common::{note, Server, Vault};
+
+const TOKEN: &str = "aaaaaaaaaaaaaaaaaaaaaaaa";
+
+#[test]
+fn graph_and_stats_leave_out_the_private_sections() {
+  let vault = Vault::with_config(&format!(
+    "auth:\n  tokens:\n    - name: me\n      token: {}\n      scopes: [read-private]\n",
+    TOKEN
+  ));
+  vault.write(
+    "a.md",
+    &note(
+      true,
+      "[b](b.md)\n\n%%private%%\n[c](c.md) ![secret](secret.png) hidden words\n%%private%%\n",
+    ),
+  );
+  vault.write("b.md", &note(true, "# B"));
+  vault.write("c.md", &note(true, "# C"));
+  vault.write("secret.png", "png");
+  let server = Server::start(&vault);
+
+  let targets = |token: Option<&str>| -> Vec<String> {
+    let graph = server.get_as("/files", token);
+    let mut targets: Vec<String> = graph["links"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|link| link["target"].as_str().unwrap().to_string())
+      .collect();
+    targets.sort();
+    targets
+  };
+  assert_eq!(targets(None), ["b"]);
+  assert_eq!(targets(Some(TOKEN)), ["b", "c"]);
+
+  let anonymous = &server.get("/")["stats"];
+  let private = &server.get_as("/", Some(TOKEN))["stats"];
+  assert_eq!(anonymous["links"], 1);
+  assert_eq!(private["links"], 2);
+  assert_eq!(anonymous["assets"], 0);
+  assert_eq!(private["assets"], 1);
+  assert!(
+    anonymous["words"].as_u64() < private["words"].as_u64(),
+    "{} {}",
+    anonymous,
+    private
+  );
+}
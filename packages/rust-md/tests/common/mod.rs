@@ -0,0 +1,200 @@
+//! Temporary vaults served by the `rust-md` binary, shared by the integration tests
+
+#![allow(dead_code)]
+
+use serde_json::Value;
+use std::{
+  collections::BTreeMap,
+  fs,
+  io::{Read, Write},
+  net::{TcpListener, TcpStream},
+  path::{Path, PathBuf},
+  process::{Child, Command, Stdio},
+  thread,
+  time::{Duration, Instant},
+};
+use tempfile::TempDir;
+
+/// Longest wait for the server and the watcher to catch up
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Vault {
+  dir: TempDir,
+}
+
+impl Vault {
+  pub fn new() -> Self {
+    Self::with_config("")
+  }
+
+  /// A vault whose config has these fields besides its root and `private.include: true`
+  pub fn with_config(fields: &str) -> Self {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("vault")).unwrap();
+    fs::write(
+      dir.path().join("config.yaml"),
+      format!("root_path: vault\nprivate:\n  include: true\n{}", fields),
+    )
+    .unwrap();
+    Self { dir }
+  }
+
+  /// Folder next to the vault, for the other vaults of the config
+  pub fn dir(&self) -> &Path {
+    self.dir.path()
+  }
+
+  pub fn root(&self) -> PathBuf {
+    self.dir.path().join("vault")
+  }
+
+  pub fn config(&self) -> PathBuf {
+    self.dir.path().join("config.yaml")
+  }
+
+  pub fn write(&self, path: &str, content: &str) {
+    let path = self.root().join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+  }
+
+  pub fn remove(&self, path: &str) {
+    fs::remove_file(self.root().join(path)).unwrap();
+  }
+
+  pub fn rename(&self, from: &str, to: &str) {
+    fs::rename(self.root().join(from), self.root().join(to)).unwrap();
+  }
+
+  pub fn command(&self) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rust-md"));
+    command.arg("--config").arg(self.config());
+    command
+  }
+
+  /// Notes of a fresh scan, by path
+  pub fn scan(&self) -> BTreeMap<String, Value> {
+    let output = self.command().args(["query", "--json"]).output().unwrap();
+    assert!(output.status.success(), "query failed: {:?}", output);
+    let notes: Vec<Value> = serde_json::from_slice(&output.stdout).unwrap();
+    notes
+      .into_iter()
+      .map(|note| (note["path"].as_str().unwrap().to_string(), note))
+      .collect()
+  }
+}
+
+pub struct Server {
+  process: Child,
+  port: u16,
+}
+
+impl Server {
+  pub fn start(vault: &Vault) -> Self {
+    let port = TcpListener::bind("127.0.0.1:0")
+      .unwrap()
+      .local_addr()
+      .unwrap()
+      .port();
+    let process = vault
+      .command()
+      .args(["serve", "--bind", "127.0.0.1", "--port", &port.to_string()])
+      .stdout(Stdio::null())
+      .stderr(Stdio::null())
+      .spawn()
+      .unwrap();
+    let server = Self { process, port };
+
+    let start = Instant::now();
+    while server.try_get("/files").is_none() {
+      assert!(start.elapsed() < TIMEOUT, "the server did not start");
+      thread::sleep(Duration::from_millis(50));
+    }
+    // Let the watcher start before editing the vault
+    thread::sleep(Duration::from_millis(500));
+    server
+  }
+
+  /// Status and body of a request, with the given token if any
+  pub fn request(&self, path: &str, token: Option<&str>) -> Option<(u16, String)> {
+    let mut stream = TcpStream::connect(("127.0.0.1", self.port)).ok()?;
+    let authorization = token
+      .map(|token| format!("Authorization: Bearer {}\r\n", token))
+      .unwrap_or_default();
+    write!(
+      stream,
+      "GET {} HTTP/1.1\r\nHost: localhost\r\nAccept: application/json\r\n{}Connection: close\r\n\r\n",
+      path, authorization
+    )
+    .ok()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok()?;
+    let (head, body) = response.split_once("\r\n\r\n")?;
+    let status = head.split(' ').nth(1)?.parse().ok()?;
+    Some((status, body.to_string()))
+  }
+
+  pub fn try_get(&self, path: &str) -> Option<String> {
+    self.request(path, None).map(|(_, body)| body)
+  }
+
+  pub fn get(&self, path: &str) -> Value {
+    self.get_as(path, None)
+  }
+
+  pub fn get_as(&self, path: &str, token: Option<&str>) -> Value {
+    let (_, body) = self.request(path, token).unwrap();
+    serde_json::from_str(&body).unwrap_or_else(|err| panic!("{} from {}: {}", err, path, body))
+  }
+
+  /// Notes of the index, by path. A note removed between the requests is left
+  /// out, the next scan comparison waits for the watcher.
+  pub fn notes(&self) -> BTreeMap<String, Value> {
+    let graph = self.get("/files");
+    graph["nodes"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .filter_map(|node| {
+        let mut note = self.get(&format!("/files/{}", node["id"].as_str().unwrap()));
+        note.as_object_mut().unwrap().remove("navigation");
+        let path = note["path"].as_str()?.to_string();
+        Some((path, note))
+      })
+      .collect()
+  }
+
+  /// Wait for the index to match a fresh scan of the vault
+  pub fn assert_matches_scan(&self, vault: &Vault) {
+    let start = Instant::now();
+    loop {
+      let (indexed, scanned) = (self.notes(), vault.scan());
+      if indexed == scanned {
+        return;
+      }
+      if start.elapsed() > TIMEOUT {
+        assert_eq!(
+          serde_json::to_string_pretty(&indexed).unwrap(),
+          serde_json::to_string_pretty(&scanned).unwrap(),
+          "the index does not match a fresh scan"
+        );
+      }
+      thread::sleep(Duration::from_millis(100));
+    }
+  }
+
+  pub fn paths(&self) -> Vec<String> {
+    self.notes().into_keys().collect()
+  }
+}
+
+impl Drop for Server {
+  fn drop(&mut self) {
+    let _ = self.process.kill();
+    let _ = self.process.wait();
+  }
+}
+
+pub fn note(public: bool, body: &str) -> String {
+  format!("---\npublic: {}\n---\n{}\n", public, body)
+}
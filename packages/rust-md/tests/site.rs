@@ -0,0 +1,32 @@
+//! Static export of a vault with `rust-md export --format html`.
+
+mod common;
+
+use common::Vault;
+use std::fs;
+
+#[test]
+fn colliding_tags_get_their_own_pages() {
+  let vault = Vault::new();
+  vault.write("a.md", "---\npublic: true\ntags: [C++, index]\n---\n# A\n");
+  vault.write("b.md", "---\npublic: true\ntags: [c]\n---\n# B\n");
+  let output = vault.dir().join("site");
+
+  let status = vault
+    .command()
+    .args(["export", "--format", "html", "--output"])
+    .arg(&output)
+    .status()
+    .unwrap();
+  assert!(status.success());
+
+  let tags = output.join("tags");
+  let page = |name: &str| fs::read_to_string(tags.join(name)).unwrap();
+  assert!(page("c.html").contains("<h1>#C++</h1>"));
+  assert!(page("c-2.html").contains("<h1>#c</h1>"));
+  assert!(page("index-2.html").contains("<h1>#index</h1>"));
+  assert!(page("index.html").contains("<h1>Tags</h1>"));
+  assert!(fs::read_to_string(output.join("b.html"))
+    .unwrap()
+    .contains("href=\"tags/c-2.html\""));
+}
@@ -0,0 +1,32 @@
+//! Several vaults served by one instance, linked with `vault:` links.
+
+mod common;
+
+use common::{note, Server, Vault};
+use std::fs;
+
+/// The href of the first link of a note's html
+fn first_href(html: &str) -> &str {
+  let start = html.find("href=\"").unwrap() + "href=\"".len();
+  let end = start + html[start..].find('"').unwrap();
+  &html[start..end]
+}
+
+#[test]
+fn links_to_another_vault_are_served() {
+  let vault = Vault::with_config("vaults:\n  - name: other\n    root_path: other\n");
+  let other = vault.dir().join("other");
+  fs::create_dir_all(other.join("sub")).unwrap();
+  fs::write(other.join("sub/b.md"), note(true, "# B")).unwrap();
+  vault.write("a.md", &note(true, "[b](vault:other/sub/b.md)"));
+  let server = Server::start(&vault);
+
+  let a = server.get("/files/a");
+  assert_eq!(a["data"]["links"][0]["target_vault"], "other");
+  let href = first_href(a["content"].as_str().unwrap());
+  assert_eq!(href, "/vaults/other/files/sub/b");
+
+  let (status, _) = server.request(href, None).unwrap();
+  assert_eq!(status, 200, "{} is not served", href);
+  assert_eq!(server.get(href)["path"], "/sub/b");
+}
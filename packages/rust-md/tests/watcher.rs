@@ -0,0 +1,84 @@
+//! The index kept up to date by the watcher must match a fresh scan of the vault.
+//!
+//! Each test serves a temporary vault, edits its files, then compares every note
+//! served by `/files` with the notes of `rust-md query --json`.
+
+mod common;
+
+use common::{note, Server, Vault};
+use std::path::{Path, PathBuf};
+
+fn file_name(path: &Path) -> &str {
+  path.file_name().unwrap().to_str().unwrap()
+}
+
+#[test]
+fn edited_note_replaces_the_indexed_one() {
+  let vault = Vault::new();
+  vault.write("a.md", &note(true, "# A"));
+  vault.write("sub/b.md", &note(true, "[a](../a.md)"));
+  let server = Server::start(&vault);
+
+  vault.write("a.md", &note(true, "# A edited"));
+  vault.write("sub/b.md", &note(true, "[a](../a.md) edited"));
+  server.assert_matches_scan(&vault);
+
+  assert_eq!(server.paths(), ["/a", "/sub/b"]);
+  let b = server.get("/files/sub/b");
+  let target = PathBuf::from(b["data"]["links"][0]["target_path"].as_str().unwrap());
+  assert_eq!(file_name(&target), "a.md");
+  assert!(target.is_file(), "link resolved against the wrong folder");
+}
+
+#[test]
+fn created_and_removed_notes() {
+  let vault = Vault::new();
+  vault.write("a.md", &note(true, "# A"));
+  vault.write("b.md", &note(true, "[c](sub/c.md)"));
+  let server = Server::start(&vault);
+
+  // `b` links to `c` before it exists
+  vault.write("sub/c.md", &note(true, "# C"));
+  vault.remove("a.md");
+  server.assert_matches_scan(&vault);
+
+  assert_eq!(server.paths(), ["/b", "/sub/c"]);
+  assert_eq!(
+    server.get("/files/b")["data"]["links"][0]["target_public"],
+    true
+  );
+}
+
+#[test]
+fn renamed_notes_and_folders() {
+  let vault = Vault::new();
+  vault.write("a.md", &note(true, "# A"));
+  vault.write("sub/b.md", &note(false, "# B"));
+  vault.write("sub/deep/c.md", &note(true, "[b](../b.md)"));
+  let server = Server::start(&vault);
+  let id = server.get("/files/a")["id"].clone();
+
+  vault.rename("a.md", "renamed.md");
+  vault.rename("sub", "moved");
+  server.assert_matches_scan(&vault);
+
+  assert_eq!(server.paths(), ["/moved/b", "/moved/deep/c", "/renamed"]);
+  assert_eq!(server.get("/files/renamed")["id"], id);
+}
+
+#[test]
+fn privacy_changes_are_applied() {
+  let vault = Vault::new();
+  vault.write("a.md", &note(false, "# A"));
+  vault.write("b.md", &note(true, "[a](a.md)"));
+  let server = Server::start(&vault);
+
+  vault.write("a.md", &note(true, "# A"));
+  server.assert_matches_scan(&vault);
+
+  assert_eq!(server.get("/files/a")["public"], true);
+  assert_eq!(
+    server.get("/files/b")["data"]["links"][0]["target_public"],
+    true
+  );
+}